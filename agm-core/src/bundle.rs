@@ -0,0 +1,195 @@
+use crate::lock::Lockfile;
+use crate::validate::is_plain_name;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const MANIFEST: &str = "agmpack.yaml";
+const PRESET_DIR: &str = "presets/";
+const MOD_DIR: &str = "mods/";
+
+/// Describes what an `.agmpack` holds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The profile the preset was exported from.
+    pub game: String,
+    pub preset: String,
+    /// Whether the mod files are in the bundle. Otherwise only their specs
+    /// are, and the lockfile says where to download them.
+    pub files: bool,
+}
+
+/// Writes a bundle. `presets` are the preset files to ship, `lockfile` pins
+/// every mod and `mods` are the mods' directories in storage by name.
+pub fn write(
+    path: &Path,
+    manifest: &Manifest,
+    presets: &[PathBuf],
+    lockfile: &Lockfile,
+    mods: &[(String, PathBuf)],
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = FileOptions::default();
+
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(serde_yaml::to_string(manifest).map_err(io::Error::other)?.as_bytes())?;
+
+    for preset_path in presets {
+        let Some(file_name) = preset_path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        zip.start_file(format!("{}{}", PRESET_DIR, file_name), options)?;
+        zip.write_all(&fs::read(preset_path)?)?;
+    }
+
+    zip.start_file(format!("{}{}.lock", PRESET_DIR, manifest.preset), options)?;
+    zip.write_all(serde_yaml::to_string(lockfile).map_err(io::Error::other)?.as_bytes())?;
+
+    for (name, storage) in mods {
+        let sidecar = storage.join(format!("{}.yaml", name));
+        for entry in WalkDir::new(storage).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() || (!manifest.files && entry.path() != sidecar) {
+                continue;
+            }
+
+            let relative = entry.path().strip_prefix(storage).map_err(io::Error::other)?;
+            let relative: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+            zip.start_file(format!("{}{}/{}", MOD_DIR, name, relative.join("/")), options)?;
+            io::copy(&mut File::open(entry.path())?, &mut zip)?;
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// An `.agmpack` opened for importing.
+pub struct Bundle {
+    archive: ZipArchive<File>,
+    pub manifest: Manifest,
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let manifest = {
+            let mut file = archive.by_name(MANIFEST).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not an AGM bundle, it has no {}", path.display(), MANIFEST),
+                )
+            })?;
+            let mut yaml = String::new();
+            file.read_to_string(&mut yaml)?;
+            serde_yaml::from_str::<Manifest>(&yaml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        };
+        if !is_plain_name(&manifest.preset) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} names an invalid preset '{}'", path.display(), manifest.preset),
+            ));
+        }
+        Ok(Self { archive, manifest })
+    }
+
+    /// The preset files in the bundle by preset name, with their content. A
+    /// name that is not a plain file name is an error.
+    pub fn presets(&mut self) -> io::Result<Vec<(String, String)>> {
+        let mut presets = Vec::new();
+        for i in 0..self.archive.len() {
+            let mut file = self.archive.by_index(i)?;
+            let Some(name) = file
+                .name()
+                .strip_prefix(PRESET_DIR)
+                .and_then(|name| name.strip_suffix(".yaml"))
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            if !is_plain_name(&name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The bundle holds a preset with the invalid name '{}'", name),
+                ));
+            }
+
+            let mut yaml = String::new();
+            file.read_to_string(&mut yaml)?;
+            presets.push((name, yaml));
+        }
+        Ok(presets)
+    }
+
+    pub fn lockfile(&mut self) -> io::Result<Option<Lockfile>> {
+        let name = format!("{}{}.lock", PRESET_DIR, self.manifest.preset);
+        let Ok(mut file) = self.archive.by_name(&name) else {
+            return Ok(None);
+        };
+
+        let mut yaml = String::new();
+        file.read_to_string(&mut yaml)?;
+        let lockfile: Lockfile = serde_yaml::from_str(&yaml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // The lockfile is saved under the preset it names
+        if lockfile.preset != self.manifest.preset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The bundle's lockfile is for preset '{}', not '{}'", lockfile.preset, self.manifest.preset),
+            ));
+        }
+        Ok(Some(lockfile))
+    }
+
+    /// The names of the mods the bundle has files for.
+    pub fn mods(&self) -> Vec<String> {
+        let mut mods: Vec<String> = self
+            .archive
+            .file_names()
+            .filter_map(|name| name.strip_prefix(MOD_DIR)?.split('/').next())
+            .filter(|name| is_plain_name(name))
+            .map(str::to_string)
+            .collect();
+        mods.sort();
+        mods.dedup();
+        mods
+    }
+
+    /// Unpacks just the spec of one mod to `dest`. Returns whether the bundle
+    /// has one.
+    pub fn extract_spec(&mut self, name: &str, dest: &Path) -> io::Result<bool> {
+        let Ok(mut file) = self.archive.by_name(&format!("{}{}/{}.yaml", MOD_DIR, name, name)) else {
+            return Ok(false);
+        };
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut file, &mut File::create(dest)?)?;
+        Ok(true)
+    }
+
+    /// Unpacks the files of one mod into `dest`.
+    pub fn extract_mod(&mut self, name: &str, dest: &Path) -> io::Result<()> {
+        let prefix = format!("{}{}/", MOD_DIR, name);
+        for i in 0..self.archive.len() {
+            let mut file = self.archive.by_index(i)?;
+            let Some(relative) = file.name().strip_prefix(&prefix).map(PathBuf::from) else {
+                continue;
+            };
+            // Never write outside the mod's directory
+            if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+                continue;
+            }
+
+            let outpath = dest.join(relative);
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&outpath)?)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use crate::lock::Lockfile;
+use crate::preset::{Include, Mod, Preset};
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+/// A mod present in both presets at different places in the order.
+#[derive(Debug)]
+pub struct Moved {
+    pub name: String,
+    /// 1-based positions in the first and the second preset.
+    pub from: usize,
+    pub to: usize,
+}
+
+/// How a mod present in both presets is set up differently.
+#[derive(Debug)]
+pub enum ModChange {
+    /// The locked versions differ.
+    Version {
+        name: String,
+        first: Option<String>,
+        second: Option<String>,
+    },
+    /// The mod is filtered to different files.
+    Files {
+        name: String,
+        first: Option<Vec<String>>,
+        second: Option<Vec<String>>,
+    },
+    Enabled { name: String, first: bool, second: bool },
+}
+
+impl fmt::Display for ModChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files = |files: &Option<Vec<String>>| match files {
+            Some(files) => format!("[{}]", files.join(", ")),
+            None => "all files".to_string(),
+        };
+        let enabled = |enabled: bool| if enabled { "enabled" } else { "disabled" };
+
+        match self {
+            ModChange::Version { name, first, second } => write!(
+                f,
+                "{}: version {} -> {}",
+                name,
+                first.as_deref().unwrap_or("unknown"),
+                second.as_deref().unwrap_or("unknown")
+            ),
+            ModChange::Files { name, first, second } => {
+                write!(f, "{}: {} -> {}", name, files(first), files(second))
+            }
+            ModChange::Enabled { name, first, second } => {
+                write!(f, "{}: {} -> {}", name, enabled(*first), enabled(*second))
+            }
+        }
+    }
+}
+
+/// What changes from one preset to another, with includes resolved.
+#[derive(Debug, Default)]
+pub struct PresetDiff {
+    /// Mods only in the second preset.
+    pub added: Vec<String>,
+    /// Mods only in the first preset.
+    pub removed: Vec<String>,
+    pub moved: Vec<Moved>,
+    pub changed: Vec<ModChange>,
+}
+
+impl PresetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two flattened presets. Versions are compared when both presets
+/// are locked.
+pub fn diff(first: &Preset, second: &Preset, first_lock: Option<&Lockfile>, second_lock: Option<&Lockfile>) -> PresetDiff {
+    let find = |preset: &Preset, name: &str| preset.mods.iter().position(|m| m.name() == name);
+    let mut result = PresetDiff::default();
+
+    for mod_entry in &second.mods {
+        if find(first, mod_entry.name()).is_none() {
+            result.added.push(mod_entry.name().to_string());
+        }
+    }
+    for mod_entry in &first.mods {
+        if find(second, mod_entry.name()).is_none() {
+            result.removed.push(mod_entry.name().to_string());
+        }
+    }
+
+    // Mods outside the longest common subsequence are the ones that moved
+    let common_first: Vec<&str> = first.mods.iter().map(Mod::name).filter(|name| find(second, name).is_some()).collect();
+    let common_second: Vec<&str> = second.mods.iter().map(Mod::name).filter(|name| find(first, name).is_some()).collect();
+    let kept = longest_common_subsequence(&common_first, &common_second);
+    for name in common_second.iter().filter(|name| !kept.contains(name)) {
+        result.moved.push(Moved {
+            name: name.to_string(),
+            from: find(first, name).unwrap_or_default() + 1,
+            to: find(second, name).unwrap_or_default() + 1,
+        });
+    }
+
+    for first_entry in &first.mods {
+        let name = first_entry.name();
+        let Some(second_entry) = second.mods.iter().find(|m| m.name() == name) else {
+            continue;
+        };
+
+        if let (Some(first_lock), Some(second_lock)) = (first_lock, second_lock) {
+            let first_version = first_lock.get(name).and_then(|m| m.version.clone());
+            let second_version = second_lock.get(name).and_then(|m| m.version.clone());
+            if first_version != second_version {
+                result.changed.push(ModChange::Version {
+                    name: name.to_string(),
+                    first: first_version,
+                    second: second_version,
+                });
+            }
+        }
+
+        let (first_files, second_files) = (file_filter(first_entry), file_filter(second_entry));
+        if first_files != second_files {
+            result.changed.push(ModChange::Files {
+                name: name.to_string(),
+                first: first_files,
+                second: second_files,
+            });
+        }
+
+        if first_entry.enabled() != second_entry.enabled() {
+            result.changed.push(ModChange::Enabled {
+                name: name.to_string(),
+                first: first_entry.enabled(),
+                second: second_entry.enabled(),
+            });
+        }
+    }
+
+    result
+}
+
+fn file_filter(mod_entry: &Mod) -> Option<Vec<String>> {
+    match mod_entry {
+        Mod::Simple(_) => None,
+        Mod::Detailed(info) => info.files.clone(),
+    }
+}
+
+fn longest_common_subsequence<'a>(first: &[&'a str], second: &[&'a str]) -> Vec<&'a str> {
+    let mut lengths = vec![vec![0usize; second.len() + 1]; first.len() + 1];
+    for i in (0..first.len()).rev() {
+        for j in (0..second.len()).rev() {
+            lengths[i][j] = if first[i] == second[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut common = Vec::new();
+    while i < first.len() && j < second.len() {
+        if first[i] == second[j] {
+            common.push(first[i]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+/// Which preset's version of a conflicting entry a merge keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    First,
+    Second,
+}
+
+/// Combines two presets as they are written, includes and all. The first
+/// preset's order is kept and mods only in the second are placed after the
+/// mod they follow there. Entries set up differently in both are settled by
+/// `choose`, which gets a description of the conflict and both entries as
+/// YAML.
+pub fn merge(
+    name: &str,
+    first: Preset,
+    second: Preset,
+    mut choose: impl FnMut(&str, &str, &str) -> io::Result<Side>,
+) -> io::Result<Preset> {
+    let mut include: Vec<Include> = first.include;
+    for second_include in second.include {
+        match include.iter().position(|i| i.name() == second_include.name()) {
+            Some(index) => {
+                let (first_yaml, second_yaml) = (to_yaml(&include[index])?, to_yaml(&second_include)?);
+                if first_yaml != second_yaml
+                    && choose(&format!("include '{}'", second_include.name()), &first_yaml, &second_yaml)? == Side::Second
+                {
+                    include[index] = second_include;
+                }
+            }
+            None => include.push(second_include),
+        }
+    }
+
+    let mut mods: Vec<Mod> = first.mods;
+    let mut previous: Option<String> = None;
+    for second_mod in second.mods {
+        let mod_name = second_mod.name().to_string();
+        match mods.iter().position(|m| m.name() == mod_name) {
+            Some(index) => {
+                let (first_yaml, second_yaml) = (to_yaml(&mods[index])?, to_yaml(&second_mod)?);
+                if first_yaml != second_yaml
+                    && choose(&format!("mod '{}'", mod_name), &first_yaml, &second_yaml)? == Side::Second
+                {
+                    mods[index] = second_mod;
+                }
+            }
+            None => {
+                let index = previous
+                    .as_deref()
+                    .and_then(|previous| mods.iter().position(|m| m.name() == previous))
+                    .map_or(0, |index| index + 1);
+                mods.insert(index, second_mod);
+            }
+        }
+        previous = Some(mod_name);
+    }
+
+    let load_order = match (first.load_order, second.load_order) {
+        (Some(first_order), Some(second_order)) if first_order != second_order => {
+            let (first_yaml, second_yaml) = (to_yaml(&first_order)?, to_yaml(&second_order)?);
+            match choose("load order", &first_yaml, &second_yaml)? {
+                Side::First => Some(first_order),
+                Side::Second => Some(second_order),
+            }
+        }
+        (first_order, second_order) => first_order.or(second_order),
+    };
+
+    Ok(Preset {
+        name: name.to_string(),
+        include,
+        mods,
+        load_order,
+    })
+}
+
+/// Renders an entry being merged for comparison and for prompts.
+fn to_yaml<T: Serialize>(value: &T) -> io::Result<String> {
+    serde_yaml::to_string(value).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(mods: &[&str]) -> Preset {
+        Preset {
+            name: "test".to_string(),
+            include: Vec::new(),
+            mods: mods.iter().map(|name| Mod::Simple(name.to_string())).collect(),
+            load_order: None,
+        }
+    }
+
+    fn moved(first: &[&str], second: &[&str]) -> Vec<(String, usize, usize)> {
+        diff(&preset(first), &preset(second), None, None)
+            .moved
+            .into_iter()
+            .map(|m| (m.name, m.from, m.to))
+            .collect()
+    }
+
+    #[test]
+    fn moving_one_mod_reports_only_that_mod() {
+        assert_eq!(moved(&["a", "b", "c", "d"], &["b", "c", "d", "a"]), vec![("a".to_string(), 1, 4)]);
+        assert_eq!(moved(&["a", "b", "c", "d"], &["a", "c", "b", "d"]), vec![("b".to_string(), 2, 3)]);
+    }
+
+    #[test]
+    fn added_and_removed_mods_do_not_count_as_moved() {
+        let result = diff(&preset(&["a", "x", "b", "c"]), &preset(&["a", "b", "y", "c"]), None, None);
+        assert!(result.moved.is_empty());
+        assert_eq!(result.added, vec!["y".to_string()]);
+        assert_eq!(result.removed, vec!["x".to_string()]);
+    }
+
+    #[test]
+    fn same_order_has_no_moves() {
+        assert!(moved(&["a", "b", "c"], &["a", "b", "c"]).is_empty());
+        assert!(moved(&[], &["a"]).is_empty());
+    }
+}
@@ -1,8 +1,8 @@
 use dirs_next::{config_dir, data_dir};
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -14,7 +14,7 @@ pub enum Error {
     NoHomeDir,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct GameConfig {
     pub profile: String,
     #[serde(default)]
@@ -23,25 +23,92 @@ pub struct GameConfig {
     pub mods: Vec<String>,
     #[serde(default)]
     pub active_preset: Option<String>,
+    /// Other names the profile goes by.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
 }
 
+/// The schema version written by this build. Older files are migrated when
+/// they are loaded.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Migrations by the version they start from, each bringing the config one
+/// version up.
+const MIGRATIONS: &[fn(&mut Config)] = &[migrate_legacy_presets];
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
+    /// Files from before versioning have none and count as version 0.
+    #[serde(default)]
+    pub version: u32,
     #[serde(default)]
     pub games: Vec<GameConfig>,
     #[serde(default)]
     pub nexus_api_key: Option<String>,
     #[serde(default)]
     pub editor: Option<String>,
+    /// Days purged storage stays in the trash, 0 keeps it until emptied.
+    #[serde(default = "default_trash_expiry_days")]
+    pub trash_expiry_days: u64,
 
-    // Legacy fields for migration
+    // Legacy fields, moved into `games` by the migration to version 1
     #[serde(default, skip_serializing)]
     pub profiles: Vec<String>,
     #[serde(default, skip_serializing)]
     pub presets: Vec<LegacyPresetConfig>,
 }
 
-// Legacy structure for migration
+fn default_trash_expiry_days() -> u64 {
+    30
+}
+
+/// Writes `content` to a temporary file next to `path`, flushes it to disk
+/// and renames it over `path`.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Version 0 to 1: the `profiles` list and the per-game `presets` entries
+/// become `games`.
+fn migrate_legacy_presets(config: &mut Config) {
+    for profile in std::mem::take(&mut config.profiles) {
+        config.get_or_create_game(&profile);
+    }
+
+    for legacy in std::mem::take(&mut config.presets) {
+        let game = config.get_or_create_game(&legacy.game);
+        for preset in legacy.presets {
+            if !game.presets.contains(&preset) {
+                game.presets.push(preset);
+            }
+        }
+        if game.active_preset.is_none() {
+            game.active_preset = legacy.active_preset;
+        }
+        for alias in legacy.aliases {
+            if alias != game.profile && !game.aliases.contains(&alias) {
+                game.aliases.push(alias);
+            }
+        }
+    }
+}
+
+// Legacy structure, see `migrate_legacy_presets`
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LegacyPresetConfig {
     pub game: String,
@@ -56,9 +123,11 @@ pub struct LegacyPresetConfig {
 impl Config {
     pub fn new() -> Self {
         Self {
+            version: CONFIG_VERSION,
             games: Vec::new(),
             nexus_api_key: None,
             editor: None,
+            trash_expiry_days: default_trash_expiry_days(),
             profiles: Vec::new(),
             presets: Vec::new(),
         }
@@ -99,9 +168,48 @@ impl Config {
         Ok(())
     }
 
+    pub fn get_config_file() -> Result<PathBuf, io::Error> {
+        Ok(Self::get_config_dir()?.join("config.yaml"))
+    }
+
     pub fn load() -> Result<Self, Error> {
-        let config_dir = Self::get_config_dir()?;
-        let config_file = config_dir.join("config.yaml");
+        let config = Self::read()?;
+        if config.version >= CONFIG_VERSION {
+            return Ok(config);
+        }
+
+        let _lock = Self::lock()?;
+        Self::load_locked()
+    }
+
+    /// Loads the config, migrating it if it is older than this build. The
+    /// lock has to be held.
+    fn load_locked() -> Result<Self, Error> {
+        let mut config = Self::read()?;
+        if config.version < CONFIG_VERSION {
+            config.migrate()?;
+        }
+        Ok(config)
+    }
+
+    /// Brings the config up to the current version. The file as it was is
+    /// kept next to it as `config.yaml.v<version>.bak`.
+    fn migrate(&mut self) -> Result<(), Error> {
+        let config_file = Self::get_config_file()?;
+        let backup_file = config_file.with_file_name(format!("config.yaml.v{}.bak", self.version));
+        if config_file.exists() && !backup_file.exists() {
+            fs::copy(&config_file, &backup_file)?;
+        }
+
+        for migration in &MIGRATIONS[self.version as usize..] {
+            migration(self);
+        }
+        self.version = CONFIG_VERSION;
+        self.write()
+    }
+
+    fn read() -> Result<Self, Error> {
+        let config_file = Self::get_config_file()?;
 
         if !config_file.exists() {
             return Ok(Self::new());
@@ -114,18 +222,48 @@ impl Config {
         }
     }
 
+    /// Loads, changes and saves the config while holding the lock, so no
+    /// other process can change it in between.
+    pub fn update<T>(f: impl FnOnce(&mut Config) -> T) -> Result<T, Error> {
+        Self::new().modify(f)
+    }
+
+    /// Like [`Config::update`], and afterwards `self` is the saved config.
+    pub fn modify<T>(&mut self, f: impl FnOnce(&mut Config) -> T) -> Result<T, Error> {
+        let _lock = Self::lock()?;
+        let mut config = Self::load_locked()?;
+        let result = f(&mut config);
+        config.write()?;
+        *self = config;
+        Ok(result)
+    }
+
+    /// Takes the advisory lock every process holds while it writes the
+    /// config. It is released when the returned file is dropped.
+    fn lock() -> Result<File, Error> {
+        let config_dir = Self::get_config_dir()?;
+        fs::create_dir_all(&config_dir)?;
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(config_dir.join("config.lock"))?;
+        lock_file.lock()?;
+        Ok(lock_file)
+    }
+
     pub fn get_socket_path() -> Result<PathBuf, io::Error> {
         let mut data_dir = Self::get_data_dir()?;
         data_dir.push("agm.sock");
         Ok(data_dir)
     }
 
-    pub fn save(&self) -> Result<(), Error> {
-        let config_dir = Self::get_config_dir()?;
-        let config_file = config_dir.join("config.yaml");
-
+    /// Replaces the file in one step, so a crash leaves either the old or
+    /// the new config behind.
+    fn write(&self) -> Result<(), Error> {
+        let config_file = Self::get_config_file()?;
         let yaml_string = serde_yaml::to_string(self)?;
-        fs::write(config_file, yaml_string)?;
+        write_atomic(&config_file, yaml_string.as_bytes())?;
         Ok(())
     }
 
@@ -138,6 +276,7 @@ impl Config {
                 presets: Vec::new(),
                 mods: Vec::new(),
                 active_preset: None,
+                aliases: Vec::new(),
             });
             self.games.last_mut().unwrap()
         }
@@ -147,6 +286,15 @@ impl Config {
         self.games.iter().find(|g| g.profile == profile_name)
     }
 
+    /// The profile a name refers to: the profile of that name, the profile
+    /// with that alias, or else a profile whose name differs only in case.
+    pub fn resolve_game(&self, name: &str) -> Option<&str> {
+        self.get_game(name)
+            .or_else(|| self.games.iter().find(|g| g.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))))
+            .or_else(|| self.games.iter().find(|g| g.profile.eq_ignore_ascii_case(name)))
+            .map(|g| g.profile.as_str())
+    }
+
     pub fn get_game_mut(&mut self, profile_name: &str) -> Option<&mut GameConfig> {
         self.games.iter_mut().find(|g| g.profile == profile_name)
     }
@@ -170,6 +318,7 @@ impl Config {
                 presets: vec![preset_name.to_string()],
                 mods: Vec::new(),
                 active_preset: None,
+                aliases: Vec::new(),
             });
         }
     }
@@ -191,6 +340,7 @@ impl Config {
                 presets: Vec::new(),
                 mods: vec![mod_name.to_string()],
                 active_preset: None,
+                aliases: Vec::new(),
             });
         }
     }
@@ -207,3 +357,66 @@ impl Config {
             .unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    const VERSION_0: &str = "\
+profiles:
+- SkyrimSE
+- Fallout4
+presets:
+- game: SkyrimSE
+  aliases:
+  - sse
+  - SkyrimSE
+  presets:
+  - vanilla
+  - heavy
+  active_preset: heavy
+- game: Starfield
+  presets:
+  - vanilla
+";
+
+    #[test]
+    fn version_0_config_is_migrated_to_games() {
+        let (_guard, _) = test_env::isolated("migrate");
+        Config::ensure_config_dirs().unwrap();
+        let config_file = Config::get_config_file().unwrap();
+        fs::write(&config_file, VERSION_0).unwrap();
+
+        let config = Config::load().unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.profiles.is_empty() && config.presets.is_empty());
+        assert_eq!(config.get_profile_names(), vec!["SkyrimSE", "Fallout4", "Starfield"]);
+
+        let skyrim = config.get_game("SkyrimSE").unwrap();
+        assert_eq!(skyrim.presets, vec!["vanilla", "heavy"]);
+        assert_eq!(skyrim.active_preset.as_deref(), Some("heavy"));
+        assert_eq!(skyrim.aliases, vec!["sse"]);
+        assert!(config.get_game("Fallout4").unwrap().presets.is_empty());
+        assert_eq!(config.get_game("Starfield").unwrap().presets, vec!["vanilla"]);
+
+        let backup = config_file.with_file_name("config.yaml.v0.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), VERSION_0);
+    }
+
+    #[test]
+    fn migrated_config_loads_unchanged() {
+        let (_guard, _) = test_env::isolated("migrate-again");
+        Config::ensure_config_dirs().unwrap();
+        let config_file = Config::get_config_file().unwrap();
+        fs::write(&config_file, VERSION_0).unwrap();
+
+        let migrated = Config::load().unwrap();
+        let written = fs::read_to_string(&config_file).unwrap();
+
+        let loaded = Config::load().unwrap();
+        assert_eq!(loaded.games, migrated.games);
+        assert_eq!(fs::read_to_string(&config_file).unwrap(), written);
+        assert!(!config_file.with_file_name(format!("config.yaml.v{}.bak", CONFIG_VERSION)).exists());
+    }
+}
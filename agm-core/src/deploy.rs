@@ -0,0 +1,376 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// A link as `(source in storage, destination)`.
+pub type Link = (PathBuf, PathBuf);
+
+/// The files of one mod as input to the link planner.
+pub struct ModFiles {
+    /// The mod's directory in storage.
+    pub storage: PathBuf,
+    /// Every file in the mod's spec, relative to `storage`, with the directory
+    /// it is placed in. Files without a placement have no directory.
+    pub files: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+/// The links AGM has created for a game, saved after every deployment so the
+/// next one knows exactly what to tear down.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Deployment {
+    pub links: Vec<Link>,
+}
+
+pub(crate) fn deployment_path(game: &str) -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("deployments").join(format!("{}.yaml", game)))
+}
+
+pub(crate) fn created_dirs_path(game: &str) -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("deployments").join(format!("{}.dirs.yaml", game)))
+}
+
+/// The directories AGM created to hold a game's links. Only these are removed
+/// again once they are empty, directories of the game or the user stay.
+pub fn created_dirs(game: &str) -> io::Result<Vec<PathBuf>> {
+    let path = created_dirs_path(game)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    serde_yaml::from_str(&fs::read_to_string(path)?).map_err(io::Error::other)
+}
+
+fn save_created_dirs(game: &str, dirs: &[PathBuf]) -> io::Result<()> {
+    let path = created_dirs_path(game)?;
+    if dirs.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let yaml_string = serde_yaml::to_string(dirs).map_err(io::Error::other)?;
+    fs::write(path, yaml_string)
+}
+
+/// Removes the directories AGM created for a game that are empty now, deepest
+/// first, and returns them.
+pub fn remove_created_dirs(game: &str) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = created_dirs(game)?;
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+
+    let mut removed = Vec::new();
+    let mut kept = Vec::new();
+    for dir in dirs {
+        let is_dir = dir.symlink_metadata().is_ok_and(|m| m.is_dir());
+        if !is_dir {
+            continue;
+        }
+        if fs::read_dir(&dir)?.next().is_some() {
+            kept.push(dir);
+            continue;
+        }
+        fs::remove_dir(&dir)?;
+        removed.push(dir);
+    }
+
+    save_created_dirs(game, &kept)?;
+    Ok(removed)
+}
+
+impl Deployment {
+    pub fn load(game: &str) -> io::Result<Option<Self>> {
+        let path = deployment_path(game)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let deployment = serde_yaml::from_str(&fs::read_to_string(path)?).map_err(io::Error::other)?;
+        Ok(Some(deployment))
+    }
+
+    pub fn save(&self, game: &str) -> io::Result<()> {
+        let path = deployment_path(game)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let yaml_string = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, yaml_string)
+    }
+
+    pub fn clear(game: &str) -> io::Result<()> {
+        let path = deployment_path(game)?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The links a redeployment removed and created.
+#[derive(Debug, Default)]
+pub struct LinkChanges {
+    pub removed: Vec<Link>,
+    pub added: Vec<Link>,
+}
+
+enum Owner {
+    One(usize),
+    Many,
+}
+
+/// Turns the files of a set of mods into `(source, destination)` links.
+///
+/// A directory is linked as a whole when one mod owns the destination subtree
+/// exclusively: every file of the mod below it is placed in the same
+/// directory, no other mod places anything below it, and the game directory
+/// has no content of its own there. Everything else is linked file by file.
+pub fn plan(mods: &[ModFiles], storage_root: &Path) -> Vec<Link> {
+    // Which mods place something at or below every destination path
+    let mut owners: HashMap<PathBuf, Owner> = HashMap::new();
+    for (index, mod_files) in mods.iter().enumerate() {
+        for (target, base) in &mod_files.files {
+            let Some(base) = base else {
+                continue;
+            };
+
+            let dest = base.join(target);
+            for ancestor in dest.ancestors() {
+                match owners.entry(ancestor.to_path_buf()) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(Owner::One(index));
+                    }
+                    Entry::Occupied(mut entry) => match entry.get() {
+                        // Everything above was recorded by an earlier file
+                        Owner::One(owner) if *owner == index => break,
+                        Owner::One(_) => {
+                            entry.insert(Owner::Many);
+                        }
+                        Owner::Many => break,
+                    },
+                }
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+    for (index, mod_files) in mods.iter().enumerate() {
+        // The one placement shared by all files below each source directory
+        let mut shared_bases: HashMap<&Path, Option<&Path>> = HashMap::new();
+        for (target, base) in &mod_files.files {
+            for prefix in directory_prefixes(target) {
+                shared_bases
+                    .entry(prefix)
+                    .and_modify(|shared| {
+                        if *shared != base.as_deref() {
+                            *shared = None;
+                        }
+                    })
+                    .or_insert(base.as_deref());
+            }
+        }
+
+        let mut linkable: HashMap<&Path, bool> = HashMap::new();
+        let mut linked_dirs: HashSet<&Path> = HashSet::new();
+
+        for (target, base) in &mod_files.files {
+            let Some(base) = base else {
+                continue;
+            };
+
+            let mut prefixes = directory_prefixes(target);
+            prefixes.reverse();
+
+            let dir = prefixes.into_iter().find(|prefix| {
+                *linkable.entry(prefix).or_insert_with(|| {
+                    shared_bases.get(prefix) == Some(&Some(base.as_path()))
+                        && matches!(owners.get(&base.join(prefix)), Some(Owner::One(owner)) if *owner == index)
+                        && is_free(&base.join(prefix), storage_root)
+                })
+            });
+
+            match dir {
+                Some(prefix) => {
+                    if linked_dirs.insert(prefix) {
+                        links.push((mod_files.storage.join(prefix), base.join(prefix)));
+                    }
+                }
+                None => links.push((mod_files.storage.join(target), base.join(target))),
+            }
+        }
+    }
+    links
+}
+
+/// Splits the change from one set of links to another into the links to
+/// remove and the links to create. Links present in both are left alone.
+pub fn diff<'a>(old: &'a [Link], new: &'a [Link]) -> (Vec<&'a Link>, Vec<&'a Link>) {
+    let old_set: HashSet<&Link> = old.iter().collect();
+    let new_set: HashSet<&Link> = new.iter().collect();
+
+    let removed = old.iter().filter(|link| !new_set.contains(link)).collect();
+    let added = new.iter().filter(|link| !old_set.contains(link)).collect();
+    (removed, added)
+}
+
+/// The directories a relative path lies in, deepest first.
+fn directory_prefixes(target: &Path) -> Vec<&Path> {
+    target
+        .ancestors()
+        .skip(1)
+        .filter(|prefix| !prefix.as_os_str().is_empty())
+        .collect()
+}
+
+/// Whether `dest` can be replaced by a link without hiding game content: it is
+/// missing, an AGM link, or a directory holding nothing but AGM links.
+fn is_free(dest: &Path, storage_root: &Path) -> bool {
+    let Ok(metadata) = dest.symlink_metadata() else {
+        return true;
+    };
+
+    if metadata.file_type().is_symlink() {
+        return fs::read_link(dest)
+            .map(|target| target.starts_with(storage_root))
+            .unwrap_or(false);
+    }
+
+    if !metadata.is_dir() {
+        return false;
+    }
+
+    WalkDir::new(dest).into_iter().all(|entry| match entry {
+        Ok(entry) if entry.path_is_symlink() => fs::read_link(entry.path())
+            .map(|target| target.starts_with(storage_root))
+            .unwrap_or(false),
+        Ok(entry) => entry.file_type().is_dir(),
+        Err(_) => false,
+    })
+}
+
+/// Creates a planned link for a game. A directory left behind by earlier file
+/// links is removed first, which fails if anything but empty directories
+/// remain in it. The directories created to hold the link are recorded.
+pub fn create_link(game: &str, source: &Path, dest: &Path) -> io::Result<()> {
+    if source.is_dir() && !dest.is_symlink() && dest.is_dir() {
+        for entry in WalkDir::new(dest).contents_first(true) {
+            fs::remove_dir(entry?.path())?;
+        }
+    }
+
+    let missing: Vec<PathBuf> = dest
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    crate::symlink::create_symlink(source, dest)?;
+
+    if !missing.is_empty() {
+        let mut dirs = created_dirs(game)?;
+        for dir in missing {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        save_created_dirs(game, &dirs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("agm-deploy-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mod_files(storage: &Path, name: &str, files: &[&str], base: &Path) -> ModFiles {
+        ModFiles {
+            storage: storage.join(name),
+            files: files.iter().map(|file| (PathBuf::from(file), Some(base.to_path_buf()))).collect(),
+        }
+    }
+
+    #[test]
+    fn subtree_owned_by_one_mod_is_linked_as_a_directory() {
+        let root = fixture_dir("owned");
+        let (storage, data) = (root.join("storage"), root.join("game").join("Data"));
+        let mods = [mod_files(&storage, "textures", &["textures/a.dds", "textures/sub/b.dds"], &data)];
+
+        assert_eq!(
+            plan(&mods, &storage),
+            vec![(storage.join("textures").join("textures"), data.join("textures"))]
+        );
+    }
+
+    #[test]
+    fn shared_directory_is_linked_file_by_file() {
+        let root = fixture_dir("shared");
+        let (storage, data) = (root.join("storage"), root.join("game").join("Data"));
+        let mods = [
+            mod_files(&storage, "first", &["textures/a.dds"], &data),
+            mod_files(&storage, "second", &["textures/b.dds"], &data),
+        ];
+
+        assert_eq!(
+            plan(&mods, &storage),
+            vec![
+                (storage.join("first").join("textures/a.dds"), data.join("textures/a.dds")),
+                (storage.join("second").join("textures/b.dds"), data.join("textures/b.dds")),
+            ]
+        );
+    }
+
+    #[test]
+    fn directory_with_game_content_is_linked_file_by_file() {
+        let root = fixture_dir("game-content");
+        let (storage, data) = (root.join("storage"), root.join("game").join("Data"));
+        fs::create_dir_all(data.join("textures")).unwrap();
+        fs::write(data.join("textures").join("vanilla.dds"), "").unwrap();
+        assert!(!is_free(&data.join("textures"), &storage));
+
+        let mods = [mod_files(&storage, "textures", &["textures/a.dds"], &data)];
+        assert_eq!(
+            plan(&mods, &storage),
+            vec![(storage.join("textures").join("textures/a.dds"), data.join("textures/a.dds"))]
+        );
+    }
+
+    #[test]
+    fn directory_holding_only_agm_links_is_free() {
+        let root = fixture_dir("agm-links");
+        let (storage, data) = (root.join("storage"), root.join("game").join("Data"));
+        fs::create_dir_all(storage.join("textures")).unwrap();
+        fs::write(storage.join("textures").join("a.dds"), "").unwrap();
+        fs::create_dir_all(data.join("textures")).unwrap();
+        crate::symlink::create_symlink(&storage.join("textures").join("a.dds"), &data.join("textures").join("a.dds")).unwrap();
+
+        assert!(is_free(&data.join("textures"), &storage));
+    }
+
+    #[test]
+    fn diff_leaves_unchanged_links_alone() {
+        let link = |name: &str| (PathBuf::from("storage").join(name), PathBuf::from("game").join(name));
+        let old = vec![link("a"), link("b")];
+        let new = vec![link("b"), link("c")];
+
+        let (removed, added) = diff(&old, &new);
+        assert_eq!(removed, vec![&link("a")]);
+        assert_eq!(added, vec![&link("c")]);
+    }
+}
@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A mod another mod relies on or clashes with, as written in a mod spec or a
+/// preset entry: either just its name or a map with a version constraint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Dependency {
+    Name(String),
+    Detailed {
+        name: String,
+        /// Comma separated constraints like `>=2.2, <3`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+        /// Where the mod can be downloaded if it is missing.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+}
+
+impl Dependency {
+    pub fn name(&self) -> &str {
+        match self {
+            Dependency::Name(name) => name,
+            Dependency::Detailed { name, .. } => name,
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            Dependency::Name(_) => None,
+            Dependency::Detailed { version, .. } => version.as_deref(),
+        }
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Dependency::Name(_) => None,
+            Dependency::Detailed { url, .. } => url.as_deref(),
+        }
+    }
+
+    /// Whether a mod at `version` satisfies this dependency's constraint. A
+    /// mod without a version only satisfies dependencies without one.
+    pub fn accepts(&self, version: Option<&str>) -> bool {
+        match (self.version(), version) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(constraint), Some(version)) => matches(constraint, version),
+        }
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version() {
+            Some(version) => write!(f, "{} {}", self.name(), version),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// Compares versions component by component. Numeric components compare as
+/// numbers and missing components count as zero, so `1.2` equals `1.2.0`.
+/// Text components such as `beta` sort before numbers, so `1.2-beta` comes
+/// before `1.2`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |v: &str| -> Vec<String> {
+        v.trim()
+            .trim_start_matches(['v', 'V'])
+            .split(['.', '-', '+', '_'])
+            .map(str::to_string)
+            .collect()
+    };
+    let (a, b) = (split(a), split(b));
+
+    for i in 0..a.len().max(b.len()) {
+        let left = a.get(i).map(String::as_str).unwrap_or("0");
+        let right = b.get(i).map(String::as_str).unwrap_or("0");
+        let ordering = match (left.parse::<u64>(), right.parse::<u64>()) {
+            (Ok(l), Ok(r)) => l.cmp(&r),
+            (Ok(_), Err(_)) => Ordering::Greater,
+            (Err(_), Ok(_)) => Ordering::Less,
+            _ => left.cmp(right),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Checks `version` against comma separated constraints using `=`, `!=`,
+/// `<`, `<=`, `>`, `>=`, `^` and `~`. A bare version means `=`. `^1.2`
+/// allows anything below the next major version (`^0.2` below `0.3`), `~1.2`
+/// anything below the next minor one. A constraint without a version never
+/// matches.
+pub fn matches(constraint: &str, version: &str) -> bool {
+    constraint.split(',').map(str::trim).filter(|c| !c.is_empty()).all(|c| {
+        let (op, wanted) = ["!=", "<=", ">=", "==", "=", "<", ">", "^", "~"]
+            .iter()
+            .find_map(|op| c.strip_prefix(op).map(|rest| (*op, rest.trim())))
+            .unwrap_or(("=", c));
+        if wanted.is_empty() {
+            return false;
+        }
+        let ordering = compare_versions(version, wanted);
+
+        match op {
+            "!=" => ordering != Ordering::Equal,
+            "<=" => ordering != Ordering::Greater,
+            ">=" => ordering != Ordering::Less,
+            "<" => ordering == Ordering::Less,
+            ">" => ordering == Ordering::Greater,
+            "^" | "~" => match upper_bound(op, wanted) {
+                Some(upper) => ordering != Ordering::Less && compare_versions(version, &upper) == Ordering::Less,
+                None => false,
+            },
+            _ => ordering == Ordering::Equal,
+        }
+    })
+}
+
+/// The first version a `^` or `~` constraint on `wanted` excludes, or `None`
+/// if the component to raise is not a number.
+fn upper_bound(op: &str, wanted: &str) -> Option<String> {
+    let parts: Vec<&str> = wanted.trim_start_matches(['v', 'V']).split(['.', '-', '+', '_']).collect();
+    let index = match op {
+        "~" => 1.min(parts.len() - 1),
+        _ => parts.iter().position(|part| *part != "0").unwrap_or(parts.len() - 1),
+    };
+
+    let raised = parts[index].parse::<u64>().ok()? + 1;
+    let mut upper: Vec<String> = parts[..index].iter().map(|part| part.to_string()).collect();
+    upper.push(raised.to_string());
+    Some(upper.join("."))
+}
+
+/// A mod of a preset as the resolver sees it, with the declarations of its
+/// spec and its preset entry combined.
+#[derive(Debug)]
+pub struct ModNode {
+    pub name: String,
+    /// Whether the mod is in storage.
+    pub installed: bool,
+    pub version: Option<String>,
+    /// Where the preset says the mod comes from.
+    pub url: Option<String>,
+    pub requires: Vec<Dependency>,
+    pub optional: Vec<Dependency>,
+    pub conflicts: Vec<Dependency>,
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    /// The preset lists a mod that is not installed.
+    NotInstalled { name: String, url: Option<String> },
+    /// A required mod is not in the preset.
+    Missing { required_by: String, dependency: Dependency },
+    /// A required or optional mod is in the preset at a version that does
+    /// not satisfy the constraint.
+    WrongVersion {
+        required_by: String,
+        dependency: Dependency,
+        found: Option<String>,
+    },
+    /// Two mods in the preset are declared incompatible.
+    Conflict {
+        name: String,
+        other: String,
+        dependency: Dependency,
+    },
+}
+
+impl Problem {
+    /// The mod to download and where from, for problems fixed by fetching
+    /// a mod.
+    pub fn fetchable(&self) -> Option<(&str, &str)> {
+        match self {
+            Problem::NotInstalled { name, url: Some(url) } => Some((name, url)),
+            Problem::Missing { dependency, .. } => dependency.url().map(|url| (dependency.name(), url)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NotInstalled { name, url: Some(_) } => write!(f, "{} is in the preset but not installed", name),
+            Problem::NotInstalled { name, url: None } => write!(
+                f,
+                "{} is in the preset but not installed, and has no url or Nexus file to download it from",
+                name
+            ),
+            Problem::Missing { required_by, dependency } => {
+                write!(f, "{} requires {}, which is not in the preset", required_by, dependency)
+            }
+            Problem::WrongVersion { required_by, dependency, found } => write!(
+                f,
+                "{} requires {}, but version {} is installed",
+                required_by,
+                dependency,
+                found.as_deref().unwrap_or("unknown")
+            ),
+            Problem::Conflict { name, other, .. } => write!(f, "{} is incompatible with {}", name, other),
+        }
+    }
+}
+
+/// Everything that keeps a preset from being activated as it is.
+#[derive(Debug, Default)]
+pub struct DependencyReport {
+    pub problems: Vec<Problem>,
+}
+
+impl DependencyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks the declarations of every mod in a preset against the others.
+pub fn resolve(mods: &[ModNode]) -> DependencyReport {
+    let mut report = DependencyReport::default();
+    let find = |name: &str| mods.iter().find(|m| m.name.eq_ignore_ascii_case(name));
+
+    for node in mods {
+        if !node.installed {
+            report.problems.push(Problem::NotInstalled {
+                name: node.name.clone(),
+                url: node.url.clone(),
+            });
+        }
+
+        for dependency in &node.requires {
+            match find(dependency.name()) {
+                None => report.problems.push(Problem::Missing {
+                    required_by: node.name.clone(),
+                    dependency: dependency.clone(),
+                }),
+                Some(found) if found.installed && !dependency.accepts(found.version.as_deref()) => {
+                    report.problems.push(Problem::WrongVersion {
+                        required_by: node.name.clone(),
+                        dependency: dependency.clone(),
+                        found: found.version.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        // Optional mods may be left out, but must fit when they are there
+        for dependency in &node.optional {
+            if let Some(found) = find(dependency.name())
+                && found.installed
+                && !dependency.accepts(found.version.as_deref())
+            {
+                report.problems.push(Problem::WrongVersion {
+                    required_by: node.name.clone(),
+                    dependency: dependency.clone(),
+                    found: found.version.clone(),
+                });
+            }
+        }
+
+        for dependency in &node.conflicts {
+            if let Some(found) = find(dependency.name())
+                && (dependency.version().is_none() || dependency.accepts(found.version.as_deref()))
+            {
+                report.problems.push(Problem::Conflict {
+                    name: node.name.clone(),
+                    other: found.name.clone(),
+                    dependency: dependency.clone(),
+                });
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_of_uneven_length_pad_with_zeros() {
+        assert_eq!(compare_versions("1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("v1.2", "1.2.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2.1", "1.2"), Ordering::Greater);
+        assert_eq!(compare_versions("1.10", "1.9.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn pre_releases_come_before_the_release() {
+        assert_eq!(compare_versions("1.2-beta", "1.2"), Ordering::Less);
+        assert_eq!(compare_versions("1.2-beta", "1.2.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.2-alpha", "1.2-beta"), Ordering::Less);
+        assert_eq!(compare_versions("1.2-beta", "1.1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn range_constraints() {
+        assert!(matches(">=5.2, <6", "5.2"));
+        assert!(matches(">=5.2, <6", "5.9.1"));
+        assert!(!matches(">=5.2, <6", "6.0"));
+        assert!(!matches(">=5.2, <6", "5.1"));
+        assert!(!matches(">=5.2", "5.2-rc1"));
+        assert!(matches("!=2", "2.0.1"));
+        assert!(matches("1.2", "1.2.0"));
+    }
+
+    #[test]
+    fn caret_constraints_stay_below_the_next_breaking_version() {
+        assert!(matches("^1.2", "1.2.0"));
+        assert!(matches("^1.2", "1.9"));
+        assert!(!matches("^1.2", "2.0"));
+        assert!(!matches("^1.2", "1.1"));
+        assert!(matches("^0.2.3", "0.2.9"));
+        assert!(!matches("^0.2.3", "0.3"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+    }
+
+    #[test]
+    fn tilde_constraints_stay_below_the_next_minor_version() {
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3"));
+        assert!(!matches("~1.2.3", "1.2.2"));
+        assert!(matches("~1", "1.9"));
+        assert!(!matches("~1", "2"));
+    }
+
+    #[test]
+    fn constraints_without_a_version_never_match() {
+        assert!(!matches(">=", "1.0"));
+        assert!(!matches("^", "1.0"));
+        assert!(!matches("^beta", "1.0"));
+        assert!(!matches("~1.x", "1.5"));
+        assert!(matches("", "1.0"));
+    }
+}
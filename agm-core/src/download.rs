@@ -0,0 +1,55 @@
+use crate::config::Config;
+use crate::nexus;
+use std::io;
+use std::path::PathBuf;
+use url::Url;
+
+pub fn get_download_dir() -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("downloads"))
+}
+
+/// Downloads a mod archive into the downloads directory and returns its path.
+/// `nxm://` links are resolved through the Nexus API first, which needs an
+/// API key.
+pub async fn download(url: &str, api_key: Option<&str>) -> io::Result<PathBuf> {
+    let parsed = Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let direct_url = if parsed.scheme() == "nxm" {
+        let (game, mod_id, file_id) = nexus::parse_nxm(&parsed).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Not a Nexus file link: {}", url))
+        })?;
+        let api_key = api_key.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Nexus API key not set. Please set it using 'agm config --nexus-api-key <key>'",
+            )
+        })?;
+        nexus::get_download_link(api_key, &game, mod_id, file_id)
+            .await
+            .map_err(|e| io::Error::other(e.to_string()))?
+    } else {
+        url.to_string()
+    };
+
+    let response = reqwest::get(&direct_url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(io::Error::other)?;
+
+    // Redirects usually end at the real file name
+    let file_name = response
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("download")
+        .to_string();
+
+    let download_dir = get_download_dir()?;
+    tokio::fs::create_dir_all(&download_dir).await?;
+
+    let path = download_dir.join(file_name);
+    let bytes = response.bytes().await.map_err(io::Error::other)?;
+    tokio::fs::write(&path, &bytes).await?;
+    Ok(path)
+}
@@ -0,0 +1,555 @@
+use crate::config::{write_atomic, Config, GameConfig};
+use crate::deploy::{created_dirs_path, deployment_path};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// How many operations are kept before the oldest are dropped.
+const MAX_OPERATIONS: usize = 50;
+
+pub fn get_history_dir() -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("history"))
+}
+
+fn index_path() -> io::Result<PathBuf> {
+    Ok(get_history_dir()?.join("index.yaml"))
+}
+
+fn operation_dir(id: u64) -> io::Result<PathBuf> {
+    Ok(get_history_dir()?.join(id.to_string()))
+}
+
+/// The seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM` in UTC.
+pub fn format_time(secs: u64) -> String {
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rest / 3600, rest % 3600 / 60)
+}
+
+/// Renames a file or directory. It is copied instead when the rename fails,
+/// as game files are often on another drive than the data dir.
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(_) if from.is_dir() && !from.is_symlink() => {
+            copy_dir(from, to)?;
+            fs::remove_dir_all(from)
+        }
+        Err(_) if from.is_file() => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Copies a directory with everything below it. Links in it are copied as
+/// links.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let dest = to.join(entry.path().strip_prefix(from).map_err(io::Error::other)?);
+        if entry.path_is_symlink() {
+            crate::symlink::create_symlink(&fs::read_link(entry.path())?, &dest)?;
+        } else if entry.file_type().is_dir() {
+            fs::create_dir_all(&dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// One recorded operation as listed by `agm history`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: u64,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub description: String,
+    /// The profiles whose files the operation changed.
+    #[serde(default)]
+    pub games: Vec<String>,
+}
+
+/// Every recorded operation, oldest first. The first `position` are in
+/// effect, the rest were undone and can be redone.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub position: usize,
+    pub operations: Vec<Operation>,
+}
+
+/// The content a file had on one side of an operation.
+#[derive(Debug, Serialize, Deserialize)]
+struct FileImage {
+    path: PathBuf,
+    /// Name of the copy in the operation's directory, `None` if the file did
+    /// not exist.
+    copy: Option<String>,
+}
+
+/// A directory the operation moved, such as storage put in the trash.
+#[derive(Debug, Serialize, Deserialize)]
+struct Move {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// A game's entry in the config on one side of an operation. Only the
+/// entries an operation touched are put back, the rest of the config may have
+/// changed since.
+#[derive(Debug, Serialize, Deserialize)]
+struct GameImage {
+    profile: String,
+    /// `None` if the game had no entry.
+    config: Option<GameConfig>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Images {
+    files: Vec<FileImage>,
+    #[serde(default)]
+    moves: Vec<Move>,
+    #[serde(default)]
+    games: Vec<GameImage>,
+}
+
+/// The entry of `profile` in the config on disk.
+fn read_game_config(profile: &str) -> io::Result<Option<GameConfig>> {
+    let config = Config::load().map_err(io::Error::other)?;
+    Ok(config.get_game(profile).cloned())
+}
+
+impl History {
+    pub fn load() -> io::Result<Self> {
+        let path = index_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(&fs::read_to_string(path)?).map_err(io::Error::other)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(get_history_dir()?)?;
+        let yaml_string = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(index_path()?, yaml_string)
+    }
+
+    /// The operation `undo` would revert.
+    pub fn undoable(&self) -> Option<&Operation> {
+        self.position.checked_sub(1).and_then(|i| self.operations.get(i))
+    }
+
+    /// The operation `redo` would apply again.
+    pub fn redoable(&self) -> Option<&Operation> {
+        self.operations.get(self.position)
+    }
+
+    /// Reverts the files of the last operation in effect and returns it.
+    pub fn undo() -> io::Result<Option<Operation>> {
+        let mut history = Self::load()?;
+        let Some(operation) = history.undoable().cloned() else {
+            return Ok(None);
+        };
+
+        swap(operation.id)?;
+        history.position -= 1;
+        history.save()?;
+        Ok(Some(operation))
+    }
+
+    /// Puts back the files of the last undone operation and returns it.
+    pub fn redo() -> io::Result<Option<Operation>> {
+        let mut history = Self::load()?;
+        let Some(operation) = history.redoable().cloned() else {
+            return Ok(None);
+        };
+
+        swap(operation.id)?;
+        history.position += 1;
+        history.save()?;
+        Ok(Some(operation))
+    }
+}
+
+/// Exchanges the stored images of an operation with the files on disk. Done
+/// on an operation in effect this undoes it and keeps what it did for a
+/// redo, and the other way around.
+fn swap(id: u64) -> io::Result<()> {
+    let dir = operation_dir(id)?;
+    let images_path = dir.join("images.yaml");
+    let mut images: Images = serde_yaml::from_str(&fs::read_to_string(&images_path)?).map_err(io::Error::other)?;
+
+    for (i, image) in images.files.iter_mut().enumerate() {
+        let current = if image.path.is_file() { Some(fs::read(&image.path)?) } else { None };
+
+        match &image.copy {
+            Some(copy) => {
+                if let Some(parent) = image.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_atomic(&image.path, &fs::read(dir.join(copy))?)?;
+            }
+            None => {
+                if current.is_some() {
+                    fs::remove_file(&image.path)?;
+                }
+            }
+        }
+
+        image.copy = match current {
+            Some(content) => {
+                let copy = format!("{}.img", i);
+                fs::write(dir.join(&copy), content)?;
+                Some(copy)
+            }
+            None => None,
+        };
+    }
+
+    // Moves are reverted newest first. One whose target is gone, like
+    // storage deleted from the trash, can no longer be moved back.
+    for moved in images.moves.iter_mut().rev() {
+        if moved.to.exists() && !moved.from.exists() {
+            if let Some(parent) = moved.from.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            rename(&moved.to, &moved.from)?;
+        }
+        std::mem::swap(&mut moved.from, &mut moved.to);
+    }
+    images.moves.reverse();
+
+    if !images.games.is_empty() {
+        Config::update(|config| {
+            for image in &mut images.games {
+                let current = config.get_game(&image.profile).cloned();
+                match image.config.take() {
+                    Some(game_config) => match config.get_game_mut(&image.profile) {
+                        Some(entry) => *entry = game_config,
+                        None => config.games.push(game_config),
+                    },
+                    None => config.remove_game(&image.profile),
+                }
+                image.config = current;
+            }
+        })
+        .map_err(io::Error::other)?;
+    }
+
+    let yaml_string = serde_yaml::to_string(&images).map_err(io::Error::other)?;
+    fs::write(images_path, yaml_string)
+}
+
+/// An operation being recorded. Files are imaged when they are tracked, so
+/// they have to be tracked before they change. Dropping it without
+/// `commit` discards the record.
+pub struct Transaction {
+    id: u64,
+    description: String,
+    dir: PathBuf,
+    images: Images,
+    dirs: Vec<PathBuf>,
+    /// Directories tracked with everything below them.
+    trees: Vec<PathBuf>,
+    games: Vec<String>,
+}
+
+impl Transaction {
+    pub fn begin(description: String) -> io::Result<Self> {
+        let history = History::load()?;
+        let id = history.operations.iter().map(|o| o.id).max().unwrap_or(0) + 1;
+
+        let dir = get_history_dir()?.join(format!("{}.pending", id));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            id,
+            description,
+            dir,
+            images: Images::default(),
+            dirs: Vec::new(),
+            trees: Vec::new(),
+            games: Vec::new(),
+        })
+    }
+
+    /// Keeps the current content of a file, or that it does not exist.
+    pub fn track_file(&mut self, path: &Path) -> io::Result<()> {
+        if self.images.files.iter().any(|image| image.path == path) {
+            return Ok(());
+        }
+
+        let copy = if path.is_file() {
+            let copy = format!("{}.img", self.images.files.len());
+            fs::copy(path, self.dir.join(&copy))?;
+            Some(copy)
+        } else {
+            None
+        };
+        self.images.files.push(FileImage { path: path.to_path_buf(), copy });
+        Ok(())
+    }
+
+    /// Tracks every file directly in `dir`. Files created there by the
+    /// operation are removed again on undo.
+    pub fn track_dir(&mut self, dir: &Path) -> io::Result<()> {
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    self.track_file(&path)?;
+                }
+            }
+        }
+        if !self.dirs.iter().any(|d| d == dir) {
+            self.dirs.push(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Tracks every file in `dir` and below it. Files created anywhere below
+    /// it by the operation are removed again on undo.
+    pub fn track_tree(&mut self, dir: &Path) -> io::Result<()> {
+        for path in files_below(dir)? {
+            self.track_file(&path)?;
+        }
+        if !self.trees.iter().any(|d| d == dir) {
+            self.trees.push(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Records that a directory was moved, so undo can move it back.
+    pub fn track_move(&mut self, from: &Path, to: &Path) {
+        self.images.moves.push(Move { from: from.to_path_buf(), to: to.to_path_buf() });
+    }
+
+    /// Tracks everything AGM keeps about a game: its entry in the config, its
+    /// profile, its presets and lockfiles, and what is deployed.
+    pub fn track_game(&mut self, game: &str) -> io::Result<()> {
+        if self.games.iter().any(|g| g == game) {
+            return Ok(());
+        }
+
+        let data_dir = Config::get_data_dir()?;
+        self.images.games.push(GameImage {
+            profile: game.to_string(),
+            config: read_game_config(game)?,
+        });
+        self.track_file(&data_dir.join("profiles").join(format!("{}.yaml", game)))?;
+        self.track_dir(&data_dir.join("presets").join(game))?;
+        self.track_file(&deployment_path(game)?)?;
+        self.track_file(&created_dirs_path(game)?)?;
+        self.games.push(game.to_string());
+        Ok(())
+    }
+
+    /// Marks the operation as one that stopped part-way, which is shown in
+    /// its description.
+    pub fn fail(&mut self) {
+        self.description.push_str(" (failed)");
+    }
+
+    /// Records the operation as the latest in effect. Undone operations can
+    /// no longer be redone after this.
+    pub fn commit(mut self) -> io::Result<()> {
+        // Files the operation created did not exist before it
+        let mut created = Vec::new();
+        for dir in &self.dirs {
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(dir)? {
+                created.push(entry?.path());
+            }
+        }
+        for tree in &self.trees {
+            created.extend(files_below(tree)?);
+        }
+        for path in created {
+            if path.is_file() && !self.images.files.iter().any(|image| image.path == path) {
+                self.images.files.push(FileImage { path, copy: None });
+            }
+        }
+
+        // An operation that changed nothing is not worth undoing
+        let mut unchanged = Vec::new();
+        for image in &self.images.files {
+            let same = match &image.copy {
+                Some(copy) => image.path.is_file() && fs::read(self.dir.join(copy))? == fs::read(&image.path)?,
+                None => !image.path.exists(),
+            };
+            unchanged.push(same);
+        }
+        for image in &self.images.games {
+            unchanged.push(read_game_config(&image.profile)? == image.config);
+        }
+        if self.images.moves.is_empty() && unchanged.iter().all(|&same| same) {
+            return Ok(());
+        }
+
+        let yaml_string = serde_yaml::to_string(&self.images).map_err(io::Error::other)?;
+        fs::write(self.dir.join("images.yaml"), yaml_string)?;
+
+        let mut history = History::load()?;
+        for dropped in history.operations.drain(history.position..) {
+            let dropped_dir = operation_dir(dropped.id)?;
+            if dropped_dir.exists() {
+                fs::remove_dir_all(dropped_dir)?;
+            }
+        }
+
+        let final_dir = operation_dir(self.id)?;
+        fs::rename(&self.dir, &final_dir)?;
+        self.dir = final_dir;
+
+        history.operations.push(Operation {
+            id: self.id,
+            time: now(),
+            description: self.description.clone(),
+            games: self.games.clone(),
+        });
+        while history.operations.len() > MAX_OPERATIONS {
+            let oldest = history.operations.remove(0);
+            let oldest_dir = operation_dir(oldest.id)?;
+            if oldest_dir.exists() {
+                fs::remove_dir_all(oldest_dir)?;
+            }
+        }
+        history.position = history.operations.len();
+        history.save()
+    }
+}
+
+/// Every file in `dir` and below it.
+fn files_below(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if self.dir.extension().is_some_and(|e| e == "pending") {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    fn game_config() -> Option<GameConfig> {
+        read_game_config("game").unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo_swap_files_dirs_and_the_game_entry() {
+        let (_guard, _) = test_env::isolated("history-round-trip");
+        let data_dir = Config::get_data_dir().unwrap();
+        let profile = data_dir.join("profiles").join("game.yaml");
+        let presets = data_dir.join("presets").join("game");
+        fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        fs::create_dir_all(&presets).unwrap();
+        fs::write(&profile, "before").unwrap();
+        fs::write(presets.join("a.yaml"), "name: a\nmods: []\n").unwrap();
+        Config::update(|config| config.add_preset_to_game("game", "a")).unwrap();
+        let before = game_config();
+
+        let mut operation = Transaction::begin("Change game".to_string()).unwrap();
+        operation.track_game("game").unwrap();
+        fs::write(&profile, "after").unwrap();
+        fs::remove_file(presets.join("a.yaml")).unwrap();
+        fs::write(presets.join("b.yaml"), "name: b\nmods: []\n").unwrap();
+        Config::update(|config| {
+            config.remove_preset_from_game("game", "a");
+            config.add_preset_to_game("game", "b");
+        })
+        .unwrap();
+        operation.commit().unwrap();
+        let after = game_config();
+
+        // Changed since and not part of the operation
+        Config::update(|config| {
+            config.editor = Some("vim".to_string());
+            config.get_or_create_game("other");
+        })
+        .unwrap();
+
+        assert_eq!(History::undo().unwrap().unwrap().description, "Change game");
+        assert_eq!(fs::read_to_string(&profile).unwrap(), "before");
+        assert!(presets.join("a.yaml").is_file());
+        assert!(!presets.join("b.yaml").exists());
+        assert_eq!(game_config(), before);
+        let config = Config::load().unwrap();
+        assert_eq!(config.editor.as_deref(), Some("vim"));
+        assert!(config.get_game("other").is_some());
+
+        History::redo().unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&profile).unwrap(), "after");
+        assert!(!presets.join("a.yaml").exists());
+        assert!(presets.join("b.yaml").is_file());
+        assert_eq!(game_config(), after);
+        assert_eq!(Config::load().unwrap().editor.as_deref(), Some("vim"));
+
+        assert!(History::redo().unwrap().is_none());
+    }
+
+    #[test]
+    fn copied_directory_keeps_files_and_links() {
+        let (_guard, root) = test_env::isolated("copy-dir");
+        let from = root.join("from");
+        fs::create_dir_all(from.join("sub").join("empty")).unwrap();
+        fs::write(from.join("sub").join("file.txt"), "content").unwrap();
+        crate::symlink::create_symlink(Path::new("sub/file.txt"), &from.join("link")).unwrap();
+
+        let to = root.join("to");
+        copy_dir(&from, &to).unwrap();
+        assert_eq!(fs::read_to_string(to.join("sub").join("file.txt")).unwrap(), "content");
+        assert!(to.join("sub").join("empty").is_dir());
+        assert_eq!(fs::read_link(to.join("link")).unwrap(), Path::new("sub/file.txt"));
+    }
+
+    #[test]
+    fn operation_that_changed_nothing_is_not_recorded() {
+        let (_guard, _) = test_env::isolated("history-unchanged");
+        let mut operation = Transaction::begin("Nothing".to_string()).unwrap();
+        operation.track_game("game").unwrap();
+        operation.commit().unwrap();
+
+        assert!(History::load().unwrap().operations.is_empty());
+    }
+}
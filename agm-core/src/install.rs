@@ -1,6 +1,9 @@
+use crate::compare::Side;
 use crate::config::Config;
+use crate::lock;
 use crate::mod_spec::{FileEntry, ModSpec};
 use crate::profile::Profile;
+use crate::steam::SteamGame;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
@@ -19,6 +22,17 @@ pub trait InstallReporter {
     fn confirm_preset_add(&self) -> io::Result<bool>;
     fn prompt_for_presets(&self, presets: &[String]) -> io::Result<Vec<String>>;
     fn confirm_profile_parts_removal(&self) -> io::Result<(bool, bool)>;
+    /// Returns the index of the chosen game, or `None` to enter a path by hand.
+    fn prompt_for_steam_game(&self, games: &[SteamGame]) -> io::Result<Option<usize>>;
+    /// Asks whether to download missing mods, given as `(name, url)`.
+    fn confirm_fetch(&self, mods: &[(&str, &str)]) -> io::Result<bool>;
+    fn download_start(&self, mod_name: &str, url: &str);
+    /// Settles a conflict while merging two presets. `first` and `second`
+    /// are the conflicting entries as YAML.
+    fn choose_merge_side(&self, conflict: &str, first: &str, second: &str) -> io::Result<Side>;
+    /// Picks the local profile to use for a bundle made for `game`, which has
+    /// no profile of that name here.
+    fn prompt_for_profile_mapping(&self, game: &str, profiles: &[String]) -> io::Result<String>;
     fn warn(&self, message: &str);
 }
 
@@ -95,6 +109,7 @@ async fn handle_file(
     reporter: &dyn InstallReporter,
     data_dir: &Path,
     mod_name: &str,
+    archive_hash: &str,
 ) -> io::Result<()> {
     let file_name = file_path
         .file_name()
@@ -161,10 +176,27 @@ async fn handle_file(
         }
     }
 
-    let mut mod_spec = ModSpec {
-        name: mod_name.to_string(),
-        url: None,
-        files: files_entries,
+    // Reinstalling keeps what was declared about the mod
+    let previous: Option<ModSpec> = fs::read_to_string(&sidecar_path_in_storage)
+        .ok()
+        .and_then(|yaml| serde_yaml::from_str(&yaml).ok());
+
+    let mut mod_spec = match previous {
+        Some(previous) => ModSpec {
+            sha256: Some(archive_hash.to_string()),
+            files: files_entries,
+            ..previous
+        },
+        None => ModSpec {
+            name: mod_name.to_string(),
+            url: None,
+            version: None,
+            requires: Vec::new(),
+            optional: Vec::new(),
+            conflicts: Vec::new(),
+            sha256: Some(archive_hash.to_string()),
+            files: files_entries,
+        },
     };
 
     reporter.review_placements(&mod_spec.name);
@@ -179,7 +211,7 @@ async fn handle_file(
     
     let sidecar_path = storage_path.join(sidecar_filename);
     let yaml_string = serde_yaml::to_string(&mod_spec)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        .map_err(io::Error::other)?;
     fs::write(sidecar_path, yaml_string)?;
 
     Ok(())
@@ -205,17 +237,17 @@ pub async fn install_mods(
         ));
     }
 
-    let profile = Profile::from_file(&profile_path);
+    let profile = Profile::from_file_resolved(&profile_path, &data_dir.join("profiles")).map_err(io::Error::other)?;
 
-    for file_path_str in files {
-        let file_path = PathBuf::from(file_path_str);
-        handle_file(&file_path, &profile, reporter, &data_dir, mod_name).await?;
+    let file_paths: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+    let archive_hash = lock::archive_hash(&file_paths)?;
+    for file_path in &file_paths {
+        handle_file(file_path, &profile, reporter, &data_dir, mod_name, &archive_hash).await?;
     }
 
     // Add mod to config after successful installation
-    let mut config = Config::load().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    config.add_mod_to_game(profile_name, mod_name);
-    config.save().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    Config::update(|config| config.add_mod_to_game(profile_name, mod_name))
+        .map_err(io::Error::other)?;
 
     Ok(())
 }
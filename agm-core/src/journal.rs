@@ -0,0 +1,201 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// A single step of a preset switch. Steps are appended to the intent log
+/// before they are applied, so a log may name a step that never happened.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Entry {
+    Begin { from: Option<String>, to: String },
+    Unlink { source: PathBuf, dest: PathBuf },
+    Link { source: PathBuf, dest: PathBuf },
+    Commit,
+}
+
+/// An open intent log for a preset switch that is in progress.
+pub struct SwitchJournal {
+    file: File,
+    path: PathBuf,
+}
+
+/// A switch read back from an intent log that was never finished.
+#[derive(Debug)]
+pub struct PendingSwitch {
+    pub game: String,
+    pub from: Option<String>,
+    pub to: String,
+    pub unlinked: Vec<(PathBuf, PathBuf)>,
+    pub linked: Vec<(PathBuf, PathBuf)>,
+    pub committed: bool,
+}
+
+pub fn get_journal_dir() -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("journal"))
+}
+
+fn journal_path(game: &str) -> io::Result<PathBuf> {
+    Ok(get_journal_dir()?.join(format!("{}.log", game)))
+}
+
+impl SwitchJournal {
+    pub fn begin(game: &str, from: Option<&str>, to: &str) -> io::Result<Self> {
+        fs::create_dir_all(get_journal_dir()?)?;
+        let path = journal_path(game)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?;
+
+        let mut journal = Self { file, path };
+        journal.record(&Entry::Begin {
+            from: from.map(|s| s.to_string()),
+            to: to.to_string(),
+        })?;
+        Ok(journal)
+    }
+
+    pub fn record(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry).map_err(io::Error::other)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+
+    /// Removes the log once the switch is fully applied and saved.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+pub fn read(game: &str) -> io::Result<Option<PendingSwitch>> {
+    let path = journal_path(game)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let reader = BufReader::new(File::open(&path)?);
+    let mut pending: Option<PendingSwitch> = None;
+
+    for line in reader.lines() {
+        // A crash can leave the last line half written
+        let Ok(line) = line else {
+            break;
+        };
+        let Ok(entry) = serde_json::from_str::<Entry>(&line) else {
+            break;
+        };
+
+        match entry {
+            Entry::Begin { from, to } => {
+                pending = Some(PendingSwitch {
+                    game: game.to_string(),
+                    from,
+                    to,
+                    unlinked: Vec::new(),
+                    linked: Vec::new(),
+                    committed: false,
+                });
+            }
+            Entry::Unlink { source, dest } => {
+                if let Some(p) = pending.as_mut() {
+                    p.unlinked.push((source, dest));
+                }
+            }
+            Entry::Link { source, dest } => {
+                if let Some(p) = pending.as_mut() {
+                    p.linked.push((source, dest));
+                }
+            }
+            Entry::Commit => {
+                if let Some(p) = pending.as_mut() {
+                    p.committed = true;
+                }
+            }
+        }
+    }
+
+    Ok(pending)
+}
+
+/// Lists every switch whose intent log is still on disk.
+pub fn pending() -> io::Result<Vec<PendingSwitch>> {
+    let journal_dir = get_journal_dir()?;
+    if !journal_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut switches = Vec::new();
+    for entry in fs::read_dir(journal_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("log") {
+            continue;
+        }
+
+        let Some(game) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        if let Some(pending) = read(game)? {
+            switches.push(pending);
+        }
+    }
+    Ok(switches)
+}
+
+pub fn clear(game: &str) -> io::Result<()> {
+    let path = journal_path(game)?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    fn link(name: &str) -> (PathBuf, PathBuf) {
+        (PathBuf::from("storage").join(name), PathBuf::from("game").join(name))
+    }
+
+    #[test]
+    fn half_written_last_line_is_ignored() {
+        let (_guard, _) = test_env::isolated("journal-truncated");
+        let mut journal = SwitchJournal::begin("game", Some("a"), "b").unwrap();
+        let (source, dest) = link("a.esp");
+        journal.record(&Entry::Unlink { source, dest }).unwrap();
+        let (source, dest) = link("b.esp");
+        journal.record(&Entry::Link { source, dest }).unwrap();
+        drop(journal);
+
+        let mut file = OpenOptions::new().append(true).open(journal_path("game").unwrap()).unwrap();
+        file.write_all(b"{\"op\":\"comm").unwrap();
+
+        let pending = read("game").unwrap().unwrap();
+        assert_eq!(pending.from.as_deref(), Some("a"));
+        assert_eq!(pending.to, "b");
+        assert_eq!(pending.unlinked, vec![link("a.esp")]);
+        assert_eq!(pending.linked, vec![link("b.esp")]);
+        assert!(!pending.committed);
+    }
+
+    #[test]
+    fn commit_entry_marks_the_switch_committed() {
+        let (_guard, _) = test_env::isolated("journal-committed");
+        let mut journal = SwitchJournal::begin("game", None, "b").unwrap();
+        journal.record(&Entry::Commit).unwrap();
+        drop(journal);
+
+        assert!(read("game").unwrap().unwrap().committed);
+        assert_eq!(pending().unwrap().len(), 1);
+
+        clear("game").unwrap();
+        assert!(read("game").unwrap().is_none());
+    }
+}
@@ -1,16 +1,41 @@
 pub mod async_runtime;
+pub mod bundle;
+pub mod compare;
 pub mod config;
+pub mod deploy;
+pub mod deps;
+pub mod download;
+pub mod history;
 pub mod install;
 pub mod ipc;
+pub mod journal;
+pub mod load_order;
+pub mod lock;
 pub mod mod_spec;
 pub mod nexus;
 pub mod preset;
 pub mod profile;
+pub mod snapshot;
+pub mod sorting;
+pub mod steam;
 pub mod symlink;
+pub mod templates;
+pub mod trash;
+pub mod validate;
+pub mod verify;
+#[cfg(test)]
+mod test_env;
 
 use crate::install::{install_mods as core_install_mods, InstallReporter};
 use crate::config::{Config, GameConfig};
+use crate::compare::PresetDiff;
+use crate::deploy::{Deployment, LinkChanges, ModFiles};
+use crate::lock::{Difference, LockedMod, Lockfile};
+use crate::deps::{DependencyReport, ModNode, Problem};
+use crate::history::{History, Operation, Transaction};
 use crate::ipc::{start_ipc_server, create_url_channel};
+use crate::journal::{Entry, PendingSwitch, SwitchJournal};
+use std::collections::HashSet;
 use std::env;
 use std::fs;
 use std::io;
@@ -18,6 +43,14 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use crate::preset::Preset;
 use crate::profile::Profile;
+use crate::snapshot::Snapshot;
+use crate::sorting::{Rules, SortInput, SortReport};
+use crate::steam::SteamGame;
+use crate::templates::Template;
+use crate::trash::TrashEntry;
+use crate::validate::Issue;
+use crate::mod_spec::ModSpec;
+use crate::verify::{BackgroundVerify, VerifyReport};
 use serde_yaml;
 use url::Url;
 
@@ -35,6 +68,34 @@ pub enum Error {
     ProfileNotFound(String),
     #[error("Preset '{0}' for game '{1}' not found")]
     PresetNotFound(String, String),
+    #[error("The preset has {} unresolved dependency problem(s)", .0.problems.len())]
+    Dependencies(DependencyReport),
+    #[error("Game '{0}' has no active preset")]
+    NoActivePreset(String),
+    #[error("Profile '{0}' has no load_order section")]
+    NoLoadOrder(String),
+    #[error(transparent)]
+    Load(#[from] crate::validate::LoadError),
+    #[error("Profile template '{0}' not found")]
+    TemplateNotFound(String),
+    #[error("Mod '{0}' is not in preset '{1}'")]
+    ModNotInPreset(String, String),
+    #[error("Mod '{0}' is not installed for game '{1}'")]
+    ModNotInstalled(String, String),
+    #[error("Preset '{0}' for game '{1}' has no lockfile")]
+    NotLocked(String, String),
+    #[error("{} installed mod(s) do not match the lockfile", .0.len())]
+    LockMismatch(Vec<Difference>),
+    #[error("The download of '{name}' does not match its locked hash (expected {expected}, got {found})")]
+    HashMismatch { name: String, expected: String, found: String },
+    #[error("Snapshot '{0}' for game '{1}' not found")]
+    SnapshotNotFound(String, String),
+    #[error("{} installed mod(s) differ from the snapshot", .0.len())]
+    SnapshotMismatch(Vec<Difference>),
+    #[error("'{0}' already names profile '{1}'")]
+    AliasTaken(String, String),
+    #[error("'{0}' is not an alias of profile '{1}'")]
+    AliasNotFound(String, String),
 }
 
 fn get_editor(config: &Config) -> String {
@@ -80,17 +141,156 @@ fn open_in_editor(editor: &str, file_path: &Path, content: Option<&str>) -> Resu
 
 pub struct Agm {
     config: Config,
+    /// The operation being recorded, joined by the calls it makes.
+    operation: Option<Transaction>,
+    /// Switches that were interrupted and recovered on startup.
+    recovered: Vec<RecoveredSwitch>,
+}
+
+/// An operation that was undone or redone.
+#[derive(Debug)]
+pub struct Replayed {
+    pub operation: Operation,
+    /// Places a link should be put back but something else is in the way.
+    pub blocked: Vec<PathBuf>,
+}
+
+/// A preset switch that was interrupted and finished or undone on startup.
+#[derive(Debug)]
+pub struct RecoveredSwitch {
+    pub game: String,
+    pub from: Option<String>,
+    pub to: String,
+    /// Whether the switch got as far as its commit and was finished, rather
+    /// than undone.
+    pub committed: bool,
+    /// Links that could not be removed or put back.
+    pub blocked: Vec<PathBuf>,
+    /// Why the plugin list could not be rewritten, if it could not.
+    pub load_order_error: Option<Error>,
 }
 
 impl Agm {
     pub fn new() -> Result<Self, Error> {
         Config::ensure_config_dirs()?;
-        let mut config = Config::load()?;
+        let config = Config::load()?;
+        let mut agm = Self { config, operation: None, recovered: Vec::new() };
+
+        // Finish or undo any preset switch that was interrupted
+        agm.recovered = agm.recover_interrupted_switches()?;
         
         // Sync mods from storage on startup
-        Self::sync_mods_from_storage(&mut config)?;
+        Self::sync_mods_from_storage(&mut agm.config)?;
+
+        if agm.config.trash_expiry_days > 0 {
+            trash::expire(agm.config.trash_expiry_days)?;
+        }
         
-        Ok(Self { config })
+        Ok(agm)
+    }
+
+    /// The switches that were interrupted and finished or undone when this
+    /// instance started.
+    pub fn recovered_switches(&self) -> &[RecoveredSwitch] {
+        &self.recovered
+    }
+
+    /// Runs `f` as one operation of the history, so it can be undone. Calls
+    /// made while an operation is recorded become part of it.
+    fn record<T>(
+        &mut self,
+        description: String,
+        game: &str,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if let Some(operation) = &mut self.operation {
+            operation.track_game(game)?;
+            return f(self);
+        }
+
+        let mut operation = Transaction::begin(description)?;
+        operation.track_game(game)?;
+        self.operation = Some(operation);
+        let result = f(self);
+        // What a failed operation changed before it failed is recorded as
+        // well, so it can be undone
+        if let Some(mut operation) = self.operation.take() {
+            if result.is_err() {
+                operation.fail();
+            }
+            let committed = operation.commit();
+            if result.is_ok() {
+                committed?;
+            }
+        }
+        result
+    }
+
+    pub fn history(&self) -> Result<History, Error> {
+        Ok(History::load()?)
+    }
+
+    /// Reverts the last recorded operation, including the links it changed.
+    pub fn undo(&mut self) -> Result<Option<Replayed>, Error> {
+        self.replay(History::undoable, History::undo)
+    }
+
+    /// Applies the last undone operation again.
+    pub fn redo(&mut self) -> Result<Option<Replayed>, Error> {
+        self.replay(History::redoable, History::redo)
+    }
+
+    fn replay(
+        &mut self,
+        next: fn(&History) -> Option<&Operation>,
+        step: fn() -> io::Result<Option<Operation>>,
+    ) -> Result<Option<Replayed>, Error> {
+        let history = History::load()?;
+        let Some(operation) = next(&history) else {
+            return Ok(None);
+        };
+
+        let mut deployed = Vec::new();
+        for game in &operation.games {
+            let links = Deployment::load(game)?.map(|d| d.links).unwrap_or_default();
+            deployed.push((game.clone(), links));
+        }
+
+        let Some(operation) = step()? else {
+            return Ok(None);
+        };
+        self.config = Config::load()?;
+
+        let mut blocked = Vec::new();
+        for (game, old_links) in deployed {
+            let new_links = Deployment::load(&game)?.map(|d| d.links).unwrap_or_default();
+            let (removed, added) = deploy::diff(&old_links, &new_links);
+
+            for (source, dest) in removed {
+                let is_ours = fs::read_link(dest).map(|target| &target == source).unwrap_or(false);
+                if is_ours {
+                    crate::symlink::remove_symlink(dest)?;
+                }
+            }
+            for (source, dest) in added {
+                if fs::read_link(dest).is_ok_and(|target| &target == source) {
+                    continue;
+                }
+                // Files put back when the links were removed stay where they are
+                if dest.is_symlink() || (dest.exists() && !(source.is_dir() && dest.is_dir())) {
+                    blocked.push(dest.clone());
+                    continue;
+                }
+                deploy::create_link(&game, source, dest)?;
+            }
+
+            if let Some(profile) = self.get_profile_by_name(&game)? {
+                let active = self.config.get_game(&game).and_then(|g| g.active_preset.clone());
+                self.write_load_order(&profile, &game, active.as_deref())?;
+            }
+        }
+
+        Ok(Some(Replayed { operation, blocked }))
     }
 
     
@@ -100,6 +300,7 @@ impl Agm {
             return Ok(());
         }
 
+        let mut found = Vec::new();
         for game_dir in fs::read_dir(storage_path)? {
             let game_dir = game_dir?;
 
@@ -130,15 +331,90 @@ impl Agm {
                 storage_mods.push(mod_name.to_string());
             }
 
-            // Update config with discovered mods
-            let game_config = config.get_or_create_game(&game_name);
-            game_config.mods = storage_mods;
+            found.push((game_name, storage_mods));
         }
 
-        config.save()?;
+        // Update config with discovered mods
+        config.modify(|config| {
+            for (game_name, storage_mods) in found {
+                config.get_or_create_game(&game_name).mods = storage_mods;
+            }
+        })?;
         Ok(())
     }
 
+    fn recover_interrupted_switches(&mut self) -> Result<Vec<RecoveredSwitch>, Error> {
+        let mut recovered = Vec::new();
+        for pending in journal::pending()? {
+            recovered.push(self.recover_switch(&pending)?);
+        }
+        Ok(recovered)
+    }
+
+    /// Rolls a switch forward if it got as far as its commit entry, otherwise
+    /// undoes every step it recorded and falls back to the previous preset.
+    /// Links that cannot be changed are reported instead of stopping the
+    /// recovery, and the plugin list is rewritten for the preset the game is
+    /// left on.
+    fn recover_switch(&mut self, pending: &PendingSwitch) -> Result<RecoveredSwitch, Error> {
+        let mut blocked = Vec::new();
+        let active_preset = if pending.committed {
+            // Only the links that changed are in the log, the rest are still
+            // recorded in the deployment from before the switch
+            let mut links = Deployment::load(&pending.game).ok().flatten().unwrap_or_default().links;
+            links.retain(|link| !pending.unlinked.contains(link));
+            for link in &pending.linked {
+                if !links.contains(link) {
+                    links.push(link.clone());
+                }
+            }
+            Deployment { links }.save(&pending.game)?;
+
+            Some(pending.to.clone())
+        } else {
+            for (source, dest) in pending.linked.iter().rev() {
+                let is_ours = fs::read_link(dest).map(|target| &target == source).unwrap_or(false);
+                if is_ours && crate::symlink::remove_symlink(dest).is_err() {
+                    blocked.push(dest.clone());
+                }
+            }
+
+            for (source, dest) in pending.unlinked.iter().rev() {
+                // The log may name an unlink that never happened
+                if fs::read_link(dest).is_ok_and(|target| &target == source) {
+                    continue;
+                }
+                if dest.exists() || dest.is_symlink() || deploy::create_link(&pending.game, source, dest).is_err() {
+                    blocked.push(dest.clone());
+                }
+            }
+
+            pending.from.clone()
+        };
+
+        let load_order_error = match self.get_profile_by_name(&pending.game) {
+            Ok(Some(profile)) => self.write_load_order(&profile, &pending.game, active_preset.as_deref()).err(),
+            Ok(None) => None,
+            Err(e) => Some(e),
+        };
+
+        self.config.modify(|config| {
+            if let Some(game_config) = config.get_game_mut(&pending.game) {
+                game_config.active_preset = active_preset;
+            }
+        })?;
+
+        journal::clear(&pending.game)?;
+        Ok(RecoveredSwitch {
+            game: pending.game.clone(),
+            from: pending.from.clone(),
+            to: pending.to.clone(),
+            committed: pending.committed,
+            blocked,
+            load_order_error,
+        })
+    }
+
 
     pub async fn install_mods(
         &self,
@@ -147,6 +423,7 @@ impl Agm {
         mod_name: &str,
         reporter: &dyn InstallReporter,
     ) -> std::io::Result<()> {
+        let profile_name = &*self.resolve_game(profile_name);
         core_install_mods(files, profile_name, mod_name, reporter).await
     }
 
@@ -157,51 +434,676 @@ impl Agm {
         mod_name: &str,
         reporter: &dyn InstallReporter,
     ) -> std::io::Result<()> {
+        let profile_name = &*self.resolve_game(profile_name);
         async_runtime::run_blocking(core_install_mods(files, profile_name, mod_name, reporter))
     }
 
-    pub fn activate_mod(&self, game: &str, mod_name: &str) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    /// Links a mod that was added to the active preset by redeploying the
+    /// preset, which only touches the links that changed.
+    pub fn activate_mod(&mut self, game: &str, mod_name: &str) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("Deploy mod '{}' for {}", mod_name, game), game, |agm| {
+            let Some(active) = agm.config.get_game(game).and_then(|g| g.active_preset.clone()) else {
+                return Ok(vec![]);
+            };
+            if agm.mod_spec(game, mod_name)?.is_none() {
+                return Err(Error::ModNotInstalled(mod_name.to_string(), game.to_string()));
+            }
+
+            let mod_storage = Config::get_data_dir()?.join("storage").join(game).join(mod_name);
+            let symlinks = agm
+                .redeploy(game, &active)?
+                .added
+                .into_iter()
+                .filter(|(source, _)| source.starts_with(&mod_storage))
+                .collect();
+            Ok(symlinks)
+        })
+    }
+
+    /// Enables or disables a mod in `preset`, or in the active preset if none
+    /// is given. A mod brought in by an included preset gets an entry of its
+    /// own that overrides the included one. If the preset is active, only the
+    /// links that change are redeployed.
+    pub fn set_mod_enabled(
+        &mut self,
+        game: &str,
+        mod_name: &str,
+        preset: Option<&str>,
+        enabled: bool,
+    ) -> Result<LinkChanges, Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("{} mod '{}' for {}", if enabled { "Enable" } else { "Disable" }, mod_name, game), game, |agm| {
+            let active = agm.config.get_game(game).and_then(|g| g.active_preset.clone());
+            let preset_name = match preset {
+                Some(preset) => preset.to_string(),
+                None => active.clone().ok_or_else(|| Error::NoActivePreset(game.to_string()))?,
+            };
+
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+            if !preset_path.exists() {
+                return Err(Error::PresetNotFound(preset_name, game.to_string()));
+            }
+
+            let mut preset = Preset::from_file(&preset_path)?;
+            match preset.mods.iter_mut().find(|m| m.name() == mod_name) {
+                Some(mod_entry) => mod_entry.set_enabled(enabled),
+                None => {
+                    let flattened = Preset::from_file_flattened(&preset_path)?;
+                    if !flattened.mods.iter().any(|m| m.name() == mod_name) {
+                        return Err(Error::ModNotInPreset(mod_name.to_string(), preset_name));
+                    }
+                    let mut mod_entry = preset::Mod::Simple(mod_name.to_string());
+                    mod_entry.set_enabled(enabled);
+                    preset.mods.push(mod_entry);
+                }
+            }
+            std::fs::write(&preset_path, serde_yaml::to_string(&preset)?)?;
+
+            if active.as_deref() != Some(preset_name.as_str()) {
+                return Ok(LinkChanges::default());
+            }
+            agm.redeploy(game, &preset_name)
+        })
+    }
+
+    /// Brings the links of the active preset up to date after it was changed,
+    /// touching only the links that differ. Journaled like a switch to the
+    /// same preset.
+    fn redeploy(&mut self, game: &str, preset: &str) -> Result<LinkChanges, Error> {
         let profile = self.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+        let old_links = self.deployed_links(&profile, game)?;
+
+        let mut journal = SwitchJournal::begin(game, Some(preset), preset)?;
+        let applied = self
+            .apply_switch(&profile, game, preset, &mut journal)
+            .and_then(|links| self.write_load_order(&profile, game, Some(preset)).map(|()| links));
+        let new_links = match applied {
+            Ok(links) => links,
+            Err(e) => {
+                drop(journal);
+                if let Some(pending) = journal::read(game)? {
+                    self.recover_switch(&pending)?;
+                }
+                return Err(e);
+            }
+        };
+        journal.record(&Entry::Commit)?;
+
+        Deployment { links: new_links.clone() }.save(game)?;
+        journal.finish()?;
+
+        let (removed, added) = deploy::diff(&old_links, &new_links);
+        Ok(LinkChanges {
+            removed: removed.into_iter().cloned().collect(),
+            added: added.into_iter().cloned().collect(),
+        })
+    }
 
+    /// Resolves where every file of a mod is placed, or `None` if the mod has
+    /// no spec in storage.
+    fn mod_files(&self, profile: &Profile, game: &str, mod_name: &str) -> Result<Option<ModFiles>, Error> {
         let storage_path = Config::get_data_dir()?.join("storage").join(game).join(mod_name);
-        let mod_spec_path = storage_path.join(format!("{}.yaml", mod_name));
+        let Some(mod_spec) = self.mod_spec(game, mod_name)? else {
+            return Ok(None);
+        };
+
+        let files = mod_spec
+            .files
+            .iter()
+            .map(|file_entry| {
+                let dest_dir = if file_entry.point.is_empty() {
+                    None
+                } else {
+                    profile.resolve_point(&file_entry.point)
+                };
+                (PathBuf::from(&file_entry.target), dest_dir)
+            })
+            .collect();
+
+        Ok(Some(ModFiles { storage: storage_path, files }))
+    }
+
+    fn mod_spec_path(game: &str, mod_name: &str) -> Result<PathBuf, Error> {
+        Ok(Config::get_data_dir()?
+            .join("storage")
+            .join(game)
+            .join(mod_name)
+            .join(format!("{}.yaml", mod_name)))
+    }
+
+    /// Where the spec of a mod imported from a bundle without its files is kept
+    /// until the mod is downloaded.
+    fn bundled_spec_path(game: &str, mod_name: &str) -> Result<PathBuf, Error> {
+        Ok(Config::get_data_dir()?
+            .join("specs")
+            .join(game)
+            .join(format!("{}.yaml", mod_name)))
+    }
 
+    /// Reads the spec a mod was installed with, or `None` if it is not in storage.
+    fn mod_spec(&self, game: &str, mod_name: &str) -> Result<Option<ModSpec>, Error> {
+        let mod_spec_path = Self::mod_spec_path(game, mod_name)?;
         if !mod_spec_path.exists() {
-            return Ok(vec![]);
+            return Ok(None);
+        }
+        Ok(Some(serde_yaml::from_str(&fs::read_to_string(&mod_spec_path)?)?))
+    }
+
+    /// Checks the dependencies and conflicts declared by the mods of a preset.
+    pub fn check_dependencies(&self, game: &str, preset_name: &str) -> Result<DependencyReport, Error> {
+        let game = &*self.resolve_game(game);
+        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+        if !preset_path.exists() {
+            return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
         }
 
-        let mut symlinks = vec![];
+        let preset = Preset::from_file_flattened(&preset_path)?;
+        let lockfile = self.lockfile(game, preset_name)?;
+        let mut nodes = Vec::new();
+        for mod_entry in preset.mods.into_iter().filter(|m| m.enabled()) {
+            let mut node = match mod_entry {
+                preset::Mod::Simple(name) => ModNode {
+                    name,
+                    installed: false,
+                    version: None,
+                    url: None,
+                    requires: Vec::new(),
+                    optional: Vec::new(),
+                    conflicts: Vec::new(),
+                },
+                preset::Mod::Detailed(info) => ModNode {
+                    name: info.name,
+                    installed: false,
+                    version: None,
+                    url: info.url.or_else(|| info.nexus.map(|nexus| nexus.to_nxm())),
+                    requires: info.requires,
+                    optional: info.optional,
+                    conflicts: info.conflicts,
+                },
+            };
 
-        let mod_spec: crate::mod_spec::ModSpec = serde_yaml::from_str(&std::fs::read_to_string(&mod_spec_path)?)?;
-        for file_entry in &mod_spec.files {
-            if file_entry.point.is_empty() {
-                return Ok(vec![]);
+            if let Some(spec) = self.mod_spec(game, &node.name)? {
+                node.installed = true;
+                node.version = spec.version;
+                node.url = node.url.or(spec.url);
+                node.requires.extend(spec.requires);
+                node.optional.extend(spec.optional);
+                node.conflicts.extend(spec.conflicts);
+            } else if let Some(locked) = lockfile.as_ref().and_then(|l| l.get(&node.name)) {
+                // Where a locked mod came from, e.g. for an imported bundle
+                node.url = node.url.or_else(|| locked.url.clone());
             }
+            nodes.push(node);
+        }
 
-            let Some(dest_dir_suffix) = profile.resolve_point(&file_entry.point) else {
-                continue;
+        Ok(deps::resolve(&nodes))
+    }
+
+    /// Downloads a mod and installs it under `mod_name`, remembering where it
+    /// came from in its spec. With `sha256` given, a download with any other
+    /// hash is refused before it is installed.
+    pub fn fetch_mod(
+        &mut self,
+        game: &str,
+        mod_name: &str,
+        url: &str,
+        sha256: Option<&str>,
+        reporter: &dyn InstallReporter,
+    ) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        let api_key = self.get_nexus_api_key().cloned();
+        let archive = async_runtime::run_blocking(download::download(url, api_key.as_deref()))?;
+
+        if let Some(expected) = sha256 {
+            let found = lock::sha256_file(&archive)?;
+            if found != expected {
+                return Err(Error::HashMismatch {
+                    name: mod_name.to_string(),
+                    expected: expected.to_string(),
+                    found,
+                });
+            }
+        }
+        self.install_mods_blocking(&[archive.display().to_string()], game, mod_name, reporter)?;
+
+        if let Some(mut spec) = self.mod_spec(game, mod_name)? {
+            // A spec from a bundle is how the exporter had the mod installed.
+            // Its placements only fit when the archive is known to match
+            let bundled_path = Self::bundled_spec_path(game, mod_name)?;
+            if bundled_path.exists() {
+                let bundled: ModSpec = serde_yaml::from_str(&fs::read_to_string(&bundled_path)?)?;
+                spec = match sha256 {
+                    Some(_) => bundled,
+                    None => ModSpec { files: spec.files, sha256: spec.sha256, ..bundled },
+                };
+                fs::remove_file(&bundled_path)?;
+            }
+            if spec.url.is_none() {
+                spec.url = Some(url.to_string());
+            }
+            fs::write(Self::mod_spec_path(game, mod_name)?, serde_yaml::to_string(&spec)?)?;
+        }
+
+        // Installing records the mod in the config on disk
+        self.config = Config::load()?;
+        Ok(())
+    }
+
+    /// Downloads and installs a mod as it was locked. The archive has to match
+    /// the locked hash, and the locked version and placements are restored.
+    pub fn install_locked(
+        &mut self,
+        game: &str,
+        locked: &LockedMod,
+        url: &str,
+        reporter: &dyn InstallReporter,
+    ) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        self.fetch_mod(game, &locked.name, url, locked.sha256.as_deref(), reporter)?;
+
+        if let Some(spec) = self.mod_spec(game, &locked.name)? {
+            let spec = ModSpec {
+                version: locked.version.clone(),
+                files: locked.files.clone(),
+                ..spec
             };
+            fs::write(Self::mod_spec_path(game, &locked.name)?, serde_yaml::to_string(&spec)?)?;
+        }
+        Ok(())
+    }
 
-            let source_path = storage_path.join(&file_entry.target);
-            let dest_path = Path::new(&profile.game.path)
-                .join(dest_dir_suffix)
-                .join(&file_entry.target);
+    /// Downloads and installs the missing mods of a preset, given as
+    /// `(name, url)`, one after the other. Mods pinned in the preset's
+    /// lockfile are installed as locked. A failed download does not stop the
+    /// rest; the mods that could not be acquired are returned with the reason.
+    pub fn acquire_mods(
+        &mut self,
+        game: &str,
+        preset: &str,
+        mods: &[(&str, &str)],
+        reporter: &dyn InstallReporter,
+    ) -> Result<Vec<(String, Error)>, Error> {
+        let game = &*self.resolve_game(game);
+        let lockfile = self.lockfile(game, preset)?;
+        let mut failed = Vec::new();
+
+        for (name, url) in mods {
+            reporter.download_start(name, url);
+            let result = match lockfile.as_ref().and_then(|lockfile| lockfile.get(name)) {
+                Some(locked) => self.install_locked(game, locked, url, reporter),
+                None => self.fetch_mod(game, name, url, None, reporter),
+            };
+            if let Err(e) = result {
+                failed.push((name.to_string(), e));
+            }
+        }
+        Ok(failed)
+    }
 
-            crate::symlink::create_symlink(&source_path, &dest_path)?;
-            symlinks.push((source_path, dest_path));
+    /// The lockfile of a preset, if it was locked.
+    pub fn lockfile(&self, game: &str, preset: &str) -> Result<Option<Lockfile>, Error> {
+        let game = &*self.resolve_game(game);
+        let lock_path = Lockfile::path(game, preset)?;
+        if !lock_path.exists() {
+            return Ok(None);
         }
-        Ok(symlinks)
+        Ok(Some(Lockfile::from_file(&lock_path)?))
+    }
+
+    /// The enabled mods of a preset with the specs they are installed with.
+    fn installed_specs(&self, game: &str, preset_name: &str) -> Result<Vec<(String, Option<ModSpec>)>, Error> {
+        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+        if !preset_path.exists() {
+            return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
+        }
+
+        let preset = Preset::from_file_flattened(&preset_path)?;
+        let mut specs = Vec::new();
+        for mod_entry in preset.mods.iter().filter(|m| m.enabled()) {
+            specs.push((mod_entry.name().to_string(), self.mod_spec(game, mod_entry.name())?));
+        }
+        Ok(specs)
+    }
+
+    /// Records how every enabled mod of a preset is installed in the preset's
+    /// lockfile. Only presets whose dependencies are met can be locked.
+    pub fn lock_preset(&self, game: &str, preset: &str) -> Result<Lockfile, Error> {
+        let game = &*self.resolve_game(game);
+        let dependencies = self.check_dependencies(game, preset)?;
+        if !dependencies.is_ok() {
+            return Err(Error::Dependencies(dependencies));
+        }
+
+        let mods = self
+            .installed_specs(game, preset)?
+            .into_iter()
+            .filter_map(|(name, spec)| spec.map(|spec| LockedMod { name, ..LockedMod::from_spec(&spec) }))
+            .collect();
+        let lockfile = Lockfile { preset: preset.to_string(), mods };
+        lockfile.save(game)?;
+        Ok(lockfile)
+    }
+
+    /// Packs a preset, the presets it includes, a lockfile and the spec of
+    /// every installed mod into an `.agmpack` at `output`. With `with_files`
+    /// the mods' files are packed too, otherwise the lockfile's urls and
+    /// hashes say where to get them.
+    pub fn export_preset(&self, game: &str, preset: &str, output: &Path, with_files: bool) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset));
+        if !preset_path.exists() {
+            return Err(Error::PresetNotFound(preset.to_string(), game.to_string()));
+        }
+
+        let mut mods = Vec::new();
+        let mut locked = Vec::new();
+        for mod_entry in Preset::from_file_flattened(&preset_path)?.mods {
+            let name = mod_entry.name().to_string();
+            if let Some(spec) = self.mod_spec(game, &name)? {
+                locked.push(LockedMod { name: name.clone(), ..LockedMod::from_spec(&spec) });
+                mods.push((name.clone(), Config::get_data_dir()?.join("storage").join(game).join(&name)));
+            }
+        }
+
+        // An existing lockfile is what the preset is meant to be
+        let lockfile = match self.lockfile(game, preset)? {
+            Some(lockfile) => lockfile,
+            None => Lockfile { preset: preset.to_string(), mods: locked },
+        };
+
+        let manifest = bundle::Manifest {
+            game: game.to_string(),
+            preset: preset.to_string(),
+            files: with_files,
+        };
+        bundle::write(output, &manifest, &Preset::include_files(&preset_path)?, &lockfile, &mods)?;
+        Ok(())
+    }
+
+    /// Recreates the presets, lockfile and mods of an `.agmpack`. A bundle made
+    /// for a game without a profile of the same name is mapped to a local
+    /// profile through the reporter. Mods already in storage are left as they
+    /// are. Returns the profile and the preset that were imported.
+    pub fn import_bundle(&mut self, path: &Path, reporter: &dyn InstallReporter) -> Result<(String, String), Error> {
+        let mut bundle = bundle::Bundle::open(path)?;
+        let bundle_game = bundle.manifest.game.clone();
+        let preset = bundle.manifest.preset.clone();
+
+        let profiles = self.get_profile_names();
+        let game = if let Some(game) = self.config.resolve_game(&bundle_game) {
+            game.to_string()
+        } else if profiles.is_empty() {
+            return Err(Error::ProfileNotFound(bundle_game));
+        } else {
+            reporter.prompt_for_profile_mapping(&bundle_game, &profiles)?
+        };
+
+        // Check every preset first so nothing is written on a conflict
+        let preset_dir = Config::get_data_dir()?.join("presets").join(&game);
+        let mut new_presets = Vec::new();
+        for (name, yaml) in bundle.presets()? {
+            let preset_path = preset_dir.join(format!("{}.yaml", name));
+            if !preset_path.exists() {
+                new_presets.push((name, preset_path, yaml));
+            } else if name == preset || fs::read_to_string(&preset_path)? != yaml {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Preset '{}' for game '{}' already exists.", name, game),
+                )
+                .into());
+            }
+        }
+
+        let lockfile = bundle.lockfile()?;
+
+        fs::create_dir_all(&preset_dir)?;
+        for (_, preset_path, yaml) in &new_presets {
+            fs::write(preset_path, yaml)?;
+        }
+        if let Some(lockfile) = lockfile {
+            lockfile.save(&game)?;
+        }
+
+        let storage_root = Config::get_data_dir()?.join("storage").join(&game);
+        let mut extracted = Vec::new();
+        for mod_name in bundle.mods() {
+            let mod_storage = storage_root.join(&mod_name);
+            if mod_storage.exists() {
+                if bundle.manifest.files {
+                    reporter.warn(&format!("Mod '{}' is already installed, keeping the installed files.", mod_name));
+                }
+                continue;
+            }
+
+            if bundle.manifest.files {
+                bundle.extract_mod(&mod_name, &mod_storage)?;
+                extracted.push(mod_name);
+            } else {
+                // A spec in storage marks the mod as installed, so without
+                // its files it waits until the mod is downloaded
+                bundle.extract_spec(&mod_name, &Self::bundled_spec_path(&game, &mod_name)?)?;
+            }
+        }
+
+        self.config.modify(|config| {
+            for (name, _, _) in &new_presets {
+                config.add_preset_to_game(&game, name);
+            }
+            for mod_name in &extracted {
+                config.add_mod_to_game(&game, mod_name);
+            }
+        })?;
+        Ok((game, preset))
+    }
+
+    /// Compares the installed mods of a preset with its lockfile.
+    pub fn outdated(&self, game: &str, preset: &str) -> Result<Vec<Difference>, Error> {
+        let game = &*self.resolve_game(game);
+        let lockfile = self
+            .lockfile(game, preset)?
+            .ok_or_else(|| Error::NotLocked(preset.to_string(), game.to_string()))?;
+        Ok(lockfile.compare(&self.installed_specs(game, preset)?))
+    }
+
+    /// Plans the links for every mod in a preset.
+    fn preset_links(&self, profile: &Profile, game: &str, preset_name: &str) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+        if !preset_path.exists() {
+            return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
+        }
+
+        let preset = Preset::from_file_flattened(&preset_path)?;
+        let mut mods = vec![];
+
+        for mod_entry in preset.mods.iter().filter(|m| m.enabled()) {
+            mods.extend(self.mod_files(profile, game, mod_entry.name())?);
+        }
+
+        let storage_root = Config::get_data_dir()?.join("storage").join(game);
+        Ok(deploy::plan(&mods, &storage_root))
+    }
+
+    /// Writes the game's plugin lists for the mods of `preset_name`. Without a
+    /// preset, every plugin AGM provides is taken off the lists.
+    fn write_load_order(&self, profile: &Profile, game: &str, preset_name: Option<&str>) -> Result<(), Error> {
+        let Some(load_order) = &profile.load_order else {
+            return Ok(());
+        };
+
+        let mut managed = HashSet::new();
+        for mod_name in self.list_mods_for_game(game)? {
+            if let Some(mod_files) = self.mod_files(profile, game, &mod_name)? {
+                managed.extend(load_order.plugins_of(&mod_files)?.iter().map(|p| p.name.to_lowercase()));
+            }
+        }
+
+        let mut active = Vec::new();
+        if let Some(preset_name) = preset_name {
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+            if !preset_path.exists() {
+                return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
+            }
+
+            let preset = Preset::from_file_flattened(&preset_path)?;
+            for mod_entry in preset.mods.iter().filter(|m| m.enabled()) {
+                if let Some(mod_files) = self.mod_files(profile, game, mod_entry.name())? {
+                    active.extend(load_order.plugins_of(&mod_files)?.into_iter().map(|p| p.name));
+                }
+            }
+            active = load_order::order(active, preset.load_order.as_deref().unwrap_or_default());
+        }
+
+        load_order.write(profile, &active, &managed)?;
+        Ok(())
+    }
+
+    /// Sorts the plugins of the active preset by their masters and the
+    /// profile's rule files, stores the result as the preset's load order and
+    /// rewrites the game's plugin lists. Nothing is changed if the
+    /// constraints form a cycle.
+    pub fn sort_load_order(&mut self, game: &str) -> Result<(SortReport, Vec<(String, String)>), Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("Sort the load order of {}", game), game, |agm| {
+            let profile = agm.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+            let load_order = profile.load_order.as_ref().ok_or_else(|| Error::NoLoadOrder(game.to_string()))?;
+            let preset_name = agm
+                .config
+                .get_game(game)
+                .and_then(|g| g.active_preset.clone())
+                .ok_or_else(|| Error::NoActivePreset(game.to_string()))?;
+
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+            if !preset_path.exists() {
+                return Err(Error::PresetNotFound(preset_name, game.to_string()));
+            }
+            let preset = Preset::from_file_flattened(&preset_path)?;
+
+            let mut plugin_files = Vec::new();
+            for mod_entry in preset.mods.iter().filter(|m| m.enabled()) {
+                if let Some(mod_files) = agm.mod_files(&profile, game, mod_entry.name())? {
+                    plugin_files.extend(load_order.plugins_of(&mod_files)?);
+                }
+            }
+
+            // Start from the current order so ties keep their place
+            let names = load_order::order(
+                plugin_files.iter().map(|p| p.name.clone()).collect(),
+                preset.load_order.as_deref().unwrap_or_default(),
+            );
+
+            let mut inputs = Vec::new();
+            let mut missing_masters = Vec::new();
+            for name in &names {
+                let Some(plugin) = plugin_files.iter().find(|p| &p.name == name) else {
+                    continue;
+                };
+                let header = sorting::read_header(&plugin.source)?.unwrap_or_default();
+
+                for master in &header.masters {
+                    let deployed = names.iter().any(|n| n.eq_ignore_ascii_case(master));
+                    let implicit = load_order.implicit.iter().any(|n| n.eq_ignore_ascii_case(master));
+                    if !deployed && !implicit && !plugin.dest.with_file_name(master).exists() {
+                        missing_masters.push((name.clone(), master.clone()));
+                    }
+                }
+                inputs.push(SortInput { name: name.clone(), header });
+            }
+
+            let mut rules = Rules::default();
+            for rules_path in &load_order.rules {
+                let Some(rules_path) = profile.expand(rules_path) else {
+                    continue;
+                };
+                if rules_path.exists() {
+                    rules.extend(Rules::from_file(&rules_path)?);
+                }
+            }
+
+            let report = sorting::sort(&inputs, &rules);
+            if report.cycle.is_none() {
+                // The sorted order covers the included presets too, so it is kept
+                // in the preset itself
+                let mut preset = Preset::from_file(&preset_path)?;
+                preset.load_order = Some(report.order.clone());
+                std::fs::write(&preset_path, serde_yaml::to_string(&preset)?)?;
+                agm.write_load_order(&profile, game, Some(&preset_name))?;
+            }
+
+            Ok((report, missing_masters))
+        })
+    }
+
+    /// The links currently deployed for a game. Deployments made before they
+    /// were recorded fall back to the plan of the active preset.
+    fn deployed_links(&self, profile: &Profile, game: &str) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        if let Some(deployment) = Deployment::load(game)? {
+            return Ok(deployment.links);
+        }
+
+        self.expected_links(profile, game)
     }
 
     pub fn set_nexus_api_key(&mut self, key: &str) -> Result<(), Error> {
-        self.config.nexus_api_key = Some(key.to_string());
-        self.config.save()?;
+        self.config.modify(|config| config.nexus_api_key = Some(key.to_string()))?;
+        Ok(())
+    }
+
+    pub fn set_trash_expiry_days(&mut self, days: u64) -> Result<(), Error> {
+        self.config.modify(|config| config.trash_expiry_days = days)?;
         Ok(())
     }
 
+    /// Moves storage to the trash as part of the operation being recorded.
+    fn trash_storage(&mut self, game: &str, mod_name: Option<&str>) -> Result<TrashEntry, Error> {
+        let entry = trash::move_to_trash(game, mod_name)?;
+        if let Some(operation) = &mut self.operation {
+            operation.track_move(&entry.origin()?, &entry.path()?);
+        }
+        Ok(entry)
+    }
+
+    /// Moves a game file to the trash as part of the operation being recorded.
+    fn trash_game_file(&mut self, game: &str, file: &Path) -> Result<TrashEntry, Error> {
+        let entry = trash::move_file_to_trash(game, file)?;
+        if let Some(operation) = &mut self.operation {
+            operation.track_move(&entry.origin()?, &entry.path()?);
+        }
+        Ok(entry)
+    }
+
+    pub fn trash_entries(&self) -> Result<Vec<TrashEntry>, Error> {
+        Ok(trash::list()?)
+    }
+
+    /// Puts purged storage back and tracks its mods again. The presets and
+    /// profile that were removed with it come back through `undo`.
+    pub fn restore_from_trash(&mut self, id: &str) -> Result<TrashEntry, Error> {
+        let entry = trash::get(id)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No trash entry '{}'", id)))?;
+        let description = match (&entry.mod_name, &entry.file) {
+            (_, Some(file)) => format!("Restore {} from the trash", file.display()),
+            (Some(mod_name), None) => format!("Restore mod '{}' of {} from the trash", mod_name, entry.game),
+            (None, None) => format!("Restore the mods of {} from the trash", entry.game),
+        };
+
+        self.record(description, &entry.game.clone(), |agm| {
+            trash::restore(&entry)?;
+            if let Some(operation) = &mut agm.operation {
+                operation.track_move(&entry.path()?, &entry.origin()?);
+            }
+            Self::sync_mods_from_storage(&mut agm.config)?;
+            Ok(entry)
+        })
+    }
+
+    /// Deletes everything in the trash for good.
+    pub fn empty_trash(&self) -> Result<Vec<TrashEntry>, Error> {
+        Ok(trash::empty()?)
+    }
+
     pub fn set_editor(&mut self, editor: &str) -> Result<(), Error> {
-        self.config.editor = Some(editor.to_string());
-        self.config.save()?;
+        self.config.modify(|config| config.editor = Some(editor.to_string()))?;
         Ok(())
     }
 
@@ -213,11 +1115,62 @@ impl Agm {
         self.config.get_profile_names()
     }
 
+    /// The profile a game name or alias refers to. Names that match no
+    /// profile are returned as they are.
+    pub fn resolve_game(&self, name: &str) -> String {
+        self.config.resolve_game(name).unwrap_or(name).to_string()
+    }
+
+    pub fn aliases(&self, game: &str) -> Vec<String> {
+        let game = &*self.resolve_game(game);
+        self.config.get_game(game).map(|g| g.aliases.clone()).unwrap_or_default()
+    }
+
+    pub fn add_alias(&mut self, game: &str, alias: &str) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        if self.config.get_game(game).is_none() {
+            return Err(Error::ProfileNotFound(game.to_string()));
+        }
+        if let Some(taken) = self.config.resolve_game(alias) {
+            return Err(Error::AliasTaken(alias.to_string(), taken.to_string()));
+        }
+
+        self.record(format!("Add alias '{}' for {}", alias, game), game, |agm| {
+            agm.config.modify(|config| {
+                if let Some(game_config) = config.get_game_mut(game) {
+                    game_config.aliases.push(alias.to_string());
+                }
+            })?;
+            Ok(())
+        })
+    }
+
+    pub fn remove_alias(&mut self, game: &str, alias: &str) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        let Some(index) = self
+            .config
+            .get_game(game)
+            .and_then(|g| g.aliases.iter().position(|a| a.eq_ignore_ascii_case(alias)))
+        else {
+            return Err(Error::AliasNotFound(alias.to_string(), game.to_string()));
+        };
+
+        self.record(format!("Remove alias '{}' of {}", alias, game), game, |agm| {
+            agm.config.modify(|config| {
+                if let Some(game_config) = config.get_game_mut(game) {
+                    game_config.aliases.remove(index);
+                }
+            })?;
+            Ok(())
+        })
+    }
+
     fn get_profile_by_name(&self, name: &str) -> Result<Option<Profile>, Error> {
         if self.config.games.iter().any(|g| g.profile == name) {
             let profile_path = Config::get_data_dir()?.join("profiles").join(format!("{}.yaml", name));
             if profile_path.exists() {
-                return Ok(Some(Profile::from_file(&profile_path)));
+                let profile_dir = Config::get_data_dir()?.join("profiles");
+                return Ok(Some(Profile::from_file_resolved(&profile_path, &profile_dir)?));
             }
         }
         Ok(None)
@@ -225,6 +1178,9 @@ impl Agm {
 
     pub fn add_profile(&mut self, game: String, name: Option<String>, content: Option<String>, game_path: Option<String>) -> Result<(), Error> {
         let profile_name = name.unwrap_or_else(|| game.clone());
+        if let Some(taken) = self.config.games.iter().find(|g| g.aliases.iter().any(|a| a.eq_ignore_ascii_case(&profile_name))) {
+            return Err(Error::AliasTaken(profile_name, taken.profile.clone()));
+        }
         let profile_path = Config::get_data_dir()?.join("profiles").join(format!("{}.yaml", profile_name));
     
         if profile_path.exists() {
@@ -247,30 +1203,135 @@ impl Agm {
         if !profile_dir.exists() {
             std::fs::create_dir_all(&profile_dir)?;
         }
-        
-        std::fs::write(&profile_path, &content_to_write)?;
-    
-        // Add to new games structure
-        self.config.get_or_create_game(&profile_name);
-        self.config.save()?;
-    
-        // Automatically create a "vanilla" preset for the new profile
-        self.add_preset(game.clone(), "vanilla".to_string(), None)?;
-    
-        Ok(())
+        
+        std::fs::write(&profile_path, &content_to_write)?;
+    
+        // Add to new games structure
+        self.config.modify(|config| {
+            config.get_or_create_game(&profile_name);
+        })?;
+    
+        // Automatically create a "vanilla" preset for the new profile
+        self.add_preset(game.clone(), "vanilla".to_string(), None)?;
+    
+        Ok(())
+    }
+
+    /// Creates a profile for a game detected by [`Agm::detect_steam_games`].
+    /// The bundled template for the game is used when there is one.
+    pub fn add_profile_from_steam(&mut self, game: String, name: Option<String>, steam_game: &SteamGame) -> Result<(), Error> {
+        let profile = match templates::for_appid(steam_game.appid) {
+            Some(template) => {
+                let mut profile = template.profile()?;
+                profile.game.name = game.clone();
+                profile.set_steam_game(steam_game);
+                profile
+            }
+            None => Profile::from_steam(&game, steam_game),
+        };
+        self.add_profile(game, name, Some(serde_yaml::to_string(&profile)?), None)
+    }
+
+    /// Creates a profile from a bundled template. The game is taken from
+    /// `steam_game` if given, otherwise from `game_path`, and otherwise the
+    /// template's default path is kept.
+    pub fn add_profile_from_template(
+        &mut self,
+        game: String,
+        name: Option<String>,
+        template: &str,
+        steam_game: Option<&SteamGame>,
+        game_path: Option<String>,
+    ) -> Result<(), Error> {
+        let template = templates::get(template).ok_or_else(|| Error::TemplateNotFound(template.to_string()))?;
+
+        let mut profile = template.profile()?;
+        profile.game.name = game.clone();
+        if let Some(steam_game) = steam_game {
+            profile.set_steam_game(steam_game);
+        } else if let Some(game_path) = game_path {
+            profile.game.path = game_path;
+        }
+        self.add_profile(game, name, Some(serde_yaml::to_string(&profile)?), None)
+    }
+
+    pub fn get_templates(&self) -> &'static [Template] {
+        templates::list()
+    }
+
+    /// Lists the games installed in the Steam libraries on this machine.
+    pub fn detect_steam_games(&self) -> Result<Vec<SteamGame>, Error> {
+        Ok(steam::detect_games()?)
+    }
+
+    pub fn edit_profile(&mut self, name: &str, content: Option<String>) -> Result<(), Error> {
+        let name = &*self.resolve_game(name);
+        self.record(format!("Edit profile '{}'", name), name, |agm| {
+            let profile_path = Config::get_data_dir()?.join("profiles").join(format!("{}.yaml", name));
+            if !profile_path.exists() {
+                return Err(Error::ProfileNotFound(name.to_string()));
+            }
+
+            let editor = get_editor(&agm.config);
+            open_in_editor(&editor, &profile_path, content.as_deref())
+        })
+    }
+
+    /// The profile's YAML as written, or with everything it extends merged in.
+    pub fn show_profile(&self, name: &str, resolved: bool) -> Result<String, Error> {
+        let name = &*self.resolve_game(name);
+        let profile_dir = Config::get_data_dir()?.join("profiles");
+        let profile_path = profile_dir.join(format!("{}.yaml", name));
+        if !profile_path.exists() {
+            return Err(Error::ProfileNotFound(name.to_string()));
+        }
+
+        if resolved {
+            Ok(Profile::from_file_resolved(&profile_path, &profile_dir)?.to_yaml())
+        } else {
+            Ok(fs::read_to_string(&profile_path)?)
+        }
     }
 
-    pub fn edit_profile(&self, name: &str, content: Option<String>) -> Result<(), Error> {
+    /// Loads and validates a profile. A file that does not parse is reported
+    /// as a single error issue.
+    pub fn check_profile(&self, name: &str) -> Result<Vec<Issue>, Error> {
+        let name = &*self.resolve_game(name);
         let profile_path = Config::get_data_dir()?.join("profiles").join(format!("{}.yaml", name));
         if !profile_path.exists() {
             return Err(Error::ProfileNotFound(name.to_string()));
         }
 
-        let editor = get_editor(&self.config);
-        open_in_editor(&editor, &profile_path, content.as_deref())
+        let profile_dir = Config::get_data_dir()?.join("profiles");
+        let profile = match Profile::from_file_resolved(&profile_path, &profile_dir) {
+            Ok(profile) => profile,
+            Err(e) => return Ok(vec![e.into()]),
+        };
+
+        let mut specs = Vec::new();
+        for mod_name in self.list_mods_for_game(name)? {
+            specs.extend(self.mod_spec(name, &mod_name)?);
+        }
+
+        Ok(validate::validate_profile(&profile, &specs))
+    }
+
+    /// Loads a preset, reporting a file that does not parse as an error issue.
+    pub fn check_preset(&self, game: &str, name: &str) -> Result<Vec<Issue>, Error> {
+        let game = &*self.resolve_game(game);
+        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", name));
+        if !preset_path.exists() {
+            return Err(Error::PresetNotFound(name.to_string(), game.to_string()));
+        }
+
+        match Preset::from_file_flattened(&preset_path) {
+            Ok(_) => Ok(Vec::new()),
+            Err(e) => Ok(vec![e.into()]),
+        }
     }
 
     pub fn list_mods_for_game(&self, game: &str) -> Result<Vec<String>, Error> {
+        let game = &*self.resolve_game(game);
         let storage_path = Config::get_data_dir()?.join("storage").join(game);
         if !storage_path.exists() {
             return Ok(Vec::new());
@@ -290,34 +1351,39 @@ impl Agm {
 
     /// Get mods for a game from config (fast lookup from tracked mods)
     pub fn get_mods(&self, game: &str) -> Vec<String> {
+        let game = &*self.resolve_game(game);
         self.config.get_mods_for_game(game)
     }
 
     pub fn remove_profile(&mut self, name: &str, remove_presets: bool, remove_mods: bool) -> Result<(), Error> {
-        if remove_presets {
-            if let Some(game_config) = self.config.get_game(name) {
-                for preset in &game_config.presets.clone() {
-                    self.remove_preset(name, preset)?;
+        let name = &*self.resolve_game(name);
+        self.record(format!("Remove profile '{}'", name), name, |agm| {
+            // Links would dangle once the profile or its storage is gone
+            if agm.get_profile_by_name(name)?.is_some() {
+                agm.purge_game(name)?;
+            }
+
+            if remove_presets {
+                if let Some(game_config) = agm.config.get_game(name) {
+                    for preset in &game_config.presets.clone() {
+                        agm.remove_preset(name, preset)?;
+                    }
                 }
             }
-        }
 
-        if remove_mods {
-            let mod_storage_path = Config::get_data_dir()?.join("storage").join(name);
-            if mod_storage_path.exists() {
-                fs::remove_dir_all(mod_storage_path)?;
+            if remove_mods && Config::get_data_dir()?.join("storage").join(name).exists() {
+                agm.trash_storage(name, None)?;
             }
-        }
 
-        let profile_path = Config::get_data_dir()?.join("profiles").join(format!("{}.yaml", name));
-        if profile_path.exists() {
-            std::fs::remove_file(profile_path)?;
-        }
+            let profile_path = Config::get_data_dir()?.join("profiles").join(format!("{}.yaml", name));
+            if profile_path.exists() {
+                std::fs::remove_file(profile_path)?;
+            }
         
-        self.config.remove_game(name);
-        self.config.save()?;
+            agm.config.modify(|config| config.remove_game(name))?;
         
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn get_presets(&self) -> &Vec<GameConfig> {
@@ -325,6 +1391,7 @@ impl Agm {
     }
 
     pub fn get_preset_names(&self, game: &str) -> Vec<String> {
+        let game = &*self.resolve_game(game);
         if let Some(game_config) = self.config.get_game(game) {
             game_config.presets.clone()
         } else {
@@ -333,6 +1400,7 @@ impl Agm {
     }
 
     pub fn is_preset_active(&self, game: &str, preset: &str) -> bool {
+        let game = &*self.resolve_game(game);
         if let Some(game_config) = self.config.get_game(game) {
             game_config.active_preset.as_deref() == Some(preset)
         } else {
@@ -341,97 +1409,176 @@ impl Agm {
     }
 
     pub fn add_preset(&mut self, game: String, name: String, content: Option<String>) -> Result<(), Error> {
-        let preset_path = Config::get_data_dir()?.join("presets").join(&game).join(format!("{}.yaml", name));
+        let game = self.resolve_game(&game);
+        self.record(format!("Add preset '{}' for {}", name, game), &game, |agm| {
+            let preset_path = Config::get_data_dir()?.join("presets").join(&game).join(format!("{}.yaml", name));
         
-        let preset_dir = Config::get_data_dir()?.join("presets").join(&game);
+            let preset_dir = Config::get_data_dir()?.join("presets").join(&game);
         
         
-        if preset_path.exists() {
-            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Preset '{}' for game '{}' already exists.", name, game)).into());
-        }
+            if preset_path.exists() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Preset '{}' for game '{}' already exists.", name, game)).into());
+            }
 
-        std::fs::create_dir_all(&preset_dir)?;
+            std::fs::create_dir_all(&preset_dir)?;
 
-        let new_preset = crate::preset::Preset::new(&name);
-        let yaml_string = serde_yaml::to_string(&new_preset)?;
+            let new_preset = crate::preset::Preset::new(&name);
+            let yaml_string = serde_yaml::to_string(&new_preset)?;
         
-        self.config.add_preset_to_game(&game, &name);
-        self.config.save()?;
+            agm.config.modify(|config| config.add_preset_to_game(&game, &name))?;
 
-        let editor = get_editor(&self.config);
-        open_in_editor(&editor, &preset_path, content.as_deref().or(Some(&yaml_string)))?;
-        Ok(())
+            let editor = get_editor(&agm.config);
+            open_in_editor(&editor, &preset_path, content.as_deref().or(Some(&yaml_string)))?;
+            Ok(())
+        })
     }
 
-    pub fn edit_preset(&mut self, game: &str, name: &str, content: Option<String>) -> Result<(), Error> {
+    /// Compares two presets of a game with their includes resolved.
+    pub fn diff_presets(&self, game: &str, first: &str, second: &str) -> Result<PresetDiff, Error> {
+        let game = &*self.resolve_game(game);
+        let first_preset = self.flattened_preset(game, first)?;
+        let second_preset = self.flattened_preset(game, second)?;
+        let first_lock = self.lockfile(game, first)?;
+        let second_lock = self.lockfile(game, second)?;
+
+        Ok(compare::diff(&first_preset, &second_preset, first_lock.as_ref(), second_lock.as_ref()))
+    }
+
+    /// Merges two presets of a game into the preset `into`, which may be one
+    /// of the two. Conflicting entries are settled through the reporter.
+    pub fn merge_presets(
+        &mut self,
+        game: &str,
+        first: &str,
+        second: &str,
+        into: &str,
+        reporter: &dyn InstallReporter,
+    ) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("Merge presets '{}' and '{}' of {} into '{}'", first, second, game, into), game, |agm| {
+            let preset_dir = Config::get_data_dir()?.join("presets").join(game);
+            let preset_path = |name: &str| preset_dir.join(format!("{}.yaml", name));
+            for name in [first, second] {
+                if !preset_path(name).exists() {
+                    return Err(Error::PresetNotFound(name.to_string(), game.to_string()));
+                }
+            }
+            if into != first && into != second && preset_path(into).exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Preset '{}' for game '{}' already exists.", into, game),
+                )
+                .into());
+            }
+
+            let merged = compare::merge(
+                into,
+                Preset::from_file(&preset_path(first))?,
+                Preset::from_file(&preset_path(second))?,
+                |conflict, first, second| reporter.choose_merge_side(conflict, first, second),
+            )?;
+            std::fs::write(preset_path(into), serde_yaml::to_string(&merged)?)?;
+
+            agm.config.modify(|config| config.add_preset_to_game(game, into))?;
+            Ok(())
+        })
+    }
+
+    fn flattened_preset(&self, game: &str, name: &str) -> Result<Preset, Error> {
         let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", name));
         if !preset_path.exists() {
-             return Err(Error::PresetNotFound(name.to_string(), game.to_string()));
+            return Err(Error::PresetNotFound(name.to_string(), game.to_string()));
         }
+        Ok(Preset::from_file_flattened(&preset_path)?)
+    }
+
+    pub fn edit_preset(&mut self, game: &str, name: &str, content: Option<String>) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("Edit preset '{}' of {}", name, game), game, |agm| {
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", name));
+            if !preset_path.exists() {
+                 return Err(Error::PresetNotFound(name.to_string(), game.to_string()));
+            }
 
-        let editor = get_editor(&self.config);
-        open_in_editor(&editor, &preset_path, content.as_deref())?;
+            let editor = get_editor(&agm.config);
+            open_in_editor(&editor, &preset_path, content.as_deref())?;
         
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn remove_preset(&mut self, game: &str, name: &str) -> Result<(), Error> {
-        let is_active = self.is_preset_active(game, name);
+        let game = &*self.resolve_game(game);
+        self.record(format!("Remove preset '{}' of {}", name, game), game, |agm| {
+            let is_active = agm.is_preset_active(game, name);
 
-        if is_active {
-            self.deactivate_preset(game)?;
-            if let Some(game_config) = self.config.get_game_mut(game) {
-                game_config.active_preset = None;
+            if is_active {
+                agm.deactivate_preset(game)?;
             }
-        }
-
-        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", name));
-        if preset_path.exists() {
-            std::fs::remove_file(preset_path)?;
-        }
 
-        self.config.remove_preset_from_game(game, name);
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", name));
+            if preset_path.exists() {
+                std::fs::remove_file(preset_path)?;
+            }
+            let lock_path = Lockfile::path(game, name)?;
+            if lock_path.exists() {
+                std::fs::remove_file(lock_path)?;
+            }
 
-        self.config.save()?;
-        Ok(())
+            agm.config.modify(|config| {
+                if is_active && let Some(game_config) = config.get_game_mut(game) {
+                    game_config.active_preset = None;
+                }
+                config.remove_preset_from_game(game, name);
+            })?;
+            Ok(())
+        })
     }
 
     pub fn add_mod_to_presets(&mut self, game: &str, mod_name: &str, presets: &[String]) -> Result<(), Error> {
-        for preset_name in presets {
-            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+        let game = &*self.resolve_game(game);
+        self.record(format!("Add mod '{}' to {} of {}", mod_name, presets.join(", "), game), game, |_| {
+            for preset_name in presets {
+                let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
             
-            if !preset_path.exists() {
-                return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
-            }
+                if !preset_path.exists() {
+                    return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
+                }
 
-            let mut preset = Preset::from_file(&preset_path);
-            preset.mods.push(crate::preset::Mod::Simple(mod_name.to_string()));
-            let yaml_string = serde_yaml::to_string(&preset)?;
-            std::fs::write(&preset_path, yaml_string)?;
-        }
-        Ok(())
+                if Preset::from_file_flattened(&preset_path)?.mods.iter().any(|m| m.name() == mod_name) {
+                    continue;
+                }
+
+                let mut preset = Preset::from_file(&preset_path)?;
+                preset.mods.push(crate::preset::Mod::Simple(mod_name.to_string()));
+                let yaml_string = serde_yaml::to_string(&preset)?;
+                std::fs::write(&preset_path, yaml_string)?;
+            }
+            Ok(())
+        })
     }
 
     pub fn add_mods_to_preset(&mut self, game: &str, preset_name: &str, mod_names: &[String]) -> Result<(), Error> {
-        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
-        if !preset_path.exists() {
-            return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
-        }
+        let game = &*self.resolve_game(game);
+        self.record(format!("Add {} to preset '{}' of {}", mod_names.join(", "), preset_name, game), game, |_| {
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
+            if !preset_path.exists() {
+                return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
+            }
 
-        let mut preset = Preset::from_file(&preset_path);
-        for mod_name in mod_names {
-            // Avoid duplicates
-            if !preset.mods.iter().any(|m| match m {
-                preset::Mod::Simple(name) => name == mod_name,
-                preset::Mod::Detailed(info) => &info.name == mod_name,
-            }) {
-                preset.mods.push(crate::preset::Mod::Simple(mod_name.to_string()));
+            let flattened = Preset::from_file_flattened(&preset_path)?;
+            let mut preset = Preset::from_file(&preset_path)?;
+            for mod_name in mod_names {
+                // Avoid duplicates, including mods an included preset brings in
+                if !flattened.mods.iter().chain(&preset.mods).any(|m| m.name() == mod_name) {
+                    preset.mods.push(crate::preset::Mod::Simple(mod_name.to_string()));
+                }
             }
-        }
 
-        let yaml_string = serde_yaml::to_string(&preset)?;
-        std::fs::write(&preset_path, yaml_string)?;
-        Ok(())
+            let yaml_string = serde_yaml::to_string(&preset)?;
+            std::fs::write(&preset_path, yaml_string)?;
+            Ok(())
+        })
     }
 
     pub fn remove_mod_from_preset(
@@ -440,168 +1587,434 @@ impl Agm {
         preset: &str,
         name: &str
     ) -> Result<(), Error> {
-        let _game_config = self.config.get_game(game)
-            .ok_or_else(|| Error::ProfileNotFound(format!("Game '{}'", game)))?;
+        let game = &*self.resolve_game(game);
+        self.record(format!("Remove mod '{}' from preset '{}' of {}", name, preset, game), game, |agm| {
+            let _game_config = agm.config.get_game(game)
+                .ok_or_else(|| Error::ProfileNotFound(format!("Game '{}'", game)))?;
 
-        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset));
-        if !preset_path.exists() {
-            return Ok(());
-        }
+            let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset));
+            if !preset_path.exists() {
+                return Ok(());
+            }
 
-        let mut preset = Preset::from_file(&preset_path);
-        preset.mods.retain(|m| match m {
-            crate::preset::Mod::Simple(mod_name) => mod_name != name,
-            crate::preset::Mod::Detailed(info) => info.name != name,
-        });
-        let yaml_string = serde_yaml::to_string(&preset)?;
-        std::fs::write(&preset_path, yaml_string)?;
-        Ok(())
+            let mut preset = Preset::from_file(&preset_path)?;
+            preset.mods.retain(|m| m.name() != name);
+
+            // Mods brought in by an included preset are excluded from it instead
+            let preset_dir = preset_path.parent().unwrap_or(Path::new("."));
+            for include in &mut preset.include {
+                let include_path = preset_dir.join(format!("{}.yaml", include.name()));
+                if include_path.exists() && Preset::from_file_flattened(&include_path)?.mods.iter().any(|m| m.name() == name) {
+                    include.exclude(name);
+                }
+            }
+            let yaml_string = serde_yaml::to_string(&preset)?;
+            std::fs::write(&preset_path, yaml_string)?;
+            Ok(())
+        })
     }
 
     pub fn remove_mod(&mut self, game: &str, name: &str, purge: bool) -> Result<(), Error> {
-        // Find the game configuration for the specified game
-        let game_config = self.config.get_game(game)
-            .ok_or_else(|| Error::ProfileNotFound(format!("Game '{}'", game)))?;
+        let game = &*self.resolve_game(game);
+        self.record(format!("{} mod '{}' of {}", if purge { "Purge" } else { "Remove" }, name, game), game, |agm| {
+            // Find the game configuration for the specified game
+            let game_config = agm.config.get_game(game)
+                .ok_or_else(|| Error::ProfileNotFound(format!("Game '{}'", game)))?;
+
+            let presets = game_config.presets.clone();
+            let preset_dir = Config::get_data_dir()?.join("presets").join(game);
+            for preset in &presets {
+                let preset_path = preset_dir.join(format!("{}.yaml", preset));
+                if !preset_path.exists() {
+                    continue;
+                }
 
-        let presets = game_config.presets.clone();
-        for preset in &presets {
-            self.remove_mod_from_preset(game, preset, name)?;
-        }
+                // The mod leaves every preset, so an exclude would only hide
+                // it from an include once it is installed again
+                let mut preset = Preset::from_file(&preset_path)?;
+                preset.mods.retain(|m| m.name() != name);
+                for include in &mut preset.include {
+                    include.unexclude(name);
+                }
+                std::fs::write(&preset_path, serde_yaml::to_string(&preset)?)?;
+            }
 
-        // Remove from config
-        self.config.remove_mod_from_game(game, name);
-        self.config.save()?;
+            // Remove from config
+            agm.config.modify(|config| config.remove_mod_from_game(game, name))?;
 
-        // If purge is requested, remove mod from storage for this specific game only
-        if purge {
-            let mod_storage_path = Config::get_data_dir()?.join("storage").join(game).join(name);
-            if mod_storage_path.exists() {
-                fs::remove_dir_all(mod_storage_path)?;
+            // If purge is requested, move the mod's storage for this specific game to the trash
+            if purge && Config::get_data_dir()?.join("storage").join(game).join(name).exists() {
+                agm.trash_storage(game, Some(name))?;
             }
-        }
         
-        Ok(())
+            Ok(())
+        })
     }
 
     
-    pub fn switch_preset(&mut self, game: &str, preset: &str) -> Result<(), Error> {
-        self.deactivate_preset(game)?;
-        self.activate_preset(game, preset)?;
+    /// Switches the active preset and starts verifying the result on a worker
+    /// thread. The returned handle can be waited on or simply dropped. The
+    /// switch is refused while mods of the preset are not installed, unless
+    /// `allow_missing` is set, in which case they are left out.
+    pub fn switch_preset(&mut self, game: &str, preset: &str, allow_missing: bool) -> Result<BackgroundVerify, Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("Switch {} to preset '{}'", game, preset), game, |agm| {
+            let profile = agm.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+
+            let mut dependencies = agm.check_dependencies(game, preset)?;
+            if allow_missing {
+                dependencies.problems.retain(|p| !matches!(p, Problem::NotInstalled { .. }));
+            }
+            if !dependencies.is_ok() {
+                return Err(Error::Dependencies(dependencies));
+            }
+            if let Some(lockfile) = agm.lockfile(game, preset)? {
+                let mismatches: Vec<Difference> = lockfile
+                    .compare(&agm.installed_specs(game, preset)?)
+                    .into_iter()
+                    .filter(Difference::is_mismatch)
+                    .collect();
+                if !mismatches.is_empty() {
+                    return Err(Error::LockMismatch(mismatches));
+                }
+            }
+            let from = agm.config.get_game(game).and_then(|g| g.active_preset.clone());
+
+            let mut journal = SwitchJournal::begin(game, from.as_deref(), preset)?;
+
+            // The plugin list is part of the switch, a failure to write it
+            // rolls the links back like any other failed step
+            let applied = agm
+                .apply_switch(&profile, game, preset, &mut journal)
+                .and_then(|links| agm.write_load_order(&profile, game, Some(preset)).map(|()| links));
+            let new_links = match applied {
+                Ok(links) => links,
+                Err(e) => {
+                    drop(journal);
+                    if let Some(pending) = journal::read(game)? {
+                        agm.recover_switch(&pending)?;
+                    }
+                    return Err(e);
+                }
+            };
 
-        if self.config.get_game(game).is_none() {
-            self.config.games.push(config::GameConfig {
-                profile: game.to_string(),
-                presets: vec![preset.to_string()],
-                mods: vec![],
-                active_preset: Some(preset.to_string()),
-            });
+            journal.record(&Entry::Commit)?;
+
+            agm.config.modify(|config| {
+                if config.get_game(game).is_none() {
+                    config.games.push(config::GameConfig {
+                        profile: game.to_string(),
+                        presets: vec![preset.to_string()],
+                        mods: vec![],
+                        active_preset: Some(preset.to_string()),
+                        aliases: vec![],
+                    });
+                }
+
+                let game_config = config
+                    .get_game_mut(game)
+                    .expect("game was just inserted");
+
+                game_config.active_preset = Some(preset.to_string());
+            })?;
+            Deployment { links: new_links.clone() }.save(game)?;
+            journal.finish()?;
+
+            let storage_root = Config::get_data_dir()?.join("storage").join(game);
+            Ok(BackgroundVerify::spawn(new_links, profile.deployment_roots(), storage_root))
+        })
+    }
+
+    /// Moves the game from its deployed links to the links of `to`, touching
+    /// only the links that differ between the two.
+    fn apply_switch(
+        &self,
+        profile: &Profile,
+        game: &str,
+        to: &str,
+        journal: &mut SwitchJournal,
+    ) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        let new_links = self.preset_links(profile, game, to)?;
+        let old_links = self.deployed_links(profile, game)?;
+        let (removed, added) = deploy::diff(&old_links, &new_links);
+
+        for (source, dest) in removed {
+            let is_ours = fs::read_link(dest).map(|target| &target == source).unwrap_or(false);
+            if !is_ours {
+                continue;
+            }
+
+            journal.record(&Entry::Unlink { source: source.clone(), dest: dest.clone() })?;
+            crate::symlink::remove_symlink(dest)?;
         }
 
-        let game_config = self
-            .config
-            .get_game_mut(game)
-            .expect("game was just inserted");
+        for (source, dest) in added {
+            journal.record(&Entry::Link { source: source.clone(), dest: dest.clone() })?;
+            deploy::create_link(game, source, dest)?;
+        }
+        Ok(new_links)
+    }
 
-        game_config.active_preset = Some(preset.to_string());
+    /// The links the active preset should have in the game directory.
+    fn expected_links(&self, profile: &Profile, game: &str) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        match self.config.get_game(game).and_then(|g| g.active_preset.as_deref()) {
+            Some(active) => self.preset_links(profile, game, active),
+            None => Ok(vec![]),
+        }
+    }
 
-        self.config.save()?;
-        Ok(())
+    pub fn verify_game(&self, game: &str) -> Result<VerifyReport, Error> {
+        let game = &*self.resolve_game(game);
+        let profile = self.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+        let expected = self.expected_links(&profile, game)?;
+
+        let storage_root = Config::get_data_dir()?.join("storage").join(game);
+        Ok(verify::verify(&expected, &profile.deployment_roots(), &storage_root)?)
     }
 
-    
+    /// Saves the active preset, every preset and lockfile of the game, the
+    /// installed versions of the active mods, the backups and the plugin
+    /// lists as a snapshot.
+    pub fn create_snapshot(&self, game: &str, name: &str) -> Result<Snapshot, Error> {
+        let game = &*self.resolve_game(game);
+        let profile = self.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+        if Snapshot::file(game, name)?.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Snapshot '{}' for game '{}' already exists.", name, game),
+            )
+            .into());
+        }
 
-    fn deactivate_preset(&mut self, game: &str) -> Result<Vec<PathBuf>, Error> {
-        let mut removed_symlinks = vec![];
+        let active_preset = self.config.get_game(game).and_then(|g| g.active_preset.clone());
+        let mut mods = Vec::new();
+        if let Some(active) = &active_preset {
+            for (_, spec) in self.installed_specs(game, active)? {
+                mods.extend(spec.as_ref().map(LockedMod::from_spec));
+            }
+        }
 
-        let game_config = match self.config.get_game(game) {
-            Some(gc) => gc,
-            None => return Ok(removed_symlinks),
+        let data_dir = Config::get_data_dir()?;
+        let snapshot_dir = Snapshot::path(game, name)?;
+        snapshot::copy_tree(&data_dir.join("presets").join(game), &snapshot_dir.join(snapshot::PRESET_DIR))?;
+        snapshot::copy_tree(&data_dir.join("backups").join(game), &snapshot_dir.join(snapshot::BACKUP_DIR))?;
+
+        let mut snapshot = Snapshot {
+            name: name.to_string(),
+            time: history::now(),
+            active_preset,
+            mods,
+            files: Vec::new(),
         };
+        snapshot.save(game, &Self::list_files(&profile))?;
+        Ok(snapshot)
+    }
 
-        let active_preset_name = match &game_config.active_preset {
-            Some(name) => name,
-            None => return Ok(removed_symlinks),
+    /// The plugin list files a profile has AGM write.
+    fn list_files(profile: &Profile) -> Vec<PathBuf> {
+        profile
+            .load_order
+            .iter()
+            .flat_map(|load_order| &load_order.files)
+            .filter_map(|list_file| profile.expand(&list_file.path))
+            .collect()
+    }
+
+    /// Every snapshot of a game, oldest first.
+    pub fn snapshots(&self, game: &str) -> Result<Vec<Snapshot>, Error> {
+        let game = &*self.resolve_game(game);
+        let mut snapshots = Vec::new();
+        for name in Snapshot::names(game)? {
+            snapshots.push(Snapshot::from_file(&Snapshot::file(game, &name)?)?);
+        }
+        snapshots.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.name.cmp(&b.name)));
+        Ok(snapshots)
+    }
+
+    /// Brings the game back to a snapshot. The game is purged, the snapshot's
+    /// presets and backups replace the current ones, its preset is activated
+    /// again and the plugin lists are put back. Files in the game directory
+    /// that the snapshot had backed up give way to the backups. The mods have
+    /// to be installed as they were when the snapshot was taken.
+    pub fn restore_snapshot(&mut self, game: &str, name: &str) -> Result<Option<BackgroundVerify>, Error> {
+        let game = &*self.resolve_game(game);
+        let snapshot_file = Snapshot::file(game, name)?;
+        if !snapshot_file.exists() {
+            return Err(Error::SnapshotNotFound(name.to_string(), game.to_string()));
+        }
+        let snapshot = Snapshot::from_file(&snapshot_file)?;
+        let profile = self.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+
+        let lockfile = Lockfile {
+            preset: snapshot.active_preset.clone().unwrap_or_default(),
+            mods: snapshot.mods.clone(),
         };
+        let mut installed = Vec::new();
+        for locked in &snapshot.mods {
+            installed.push((locked.name.clone(), self.mod_spec(game, &locked.name)?));
+        }
+        let differences = lockfile.compare(&installed);
+        if !differences.is_empty() {
+            return Err(Error::SnapshotMismatch(differences));
+        }
 
-        let profile = self
-            .get_profile_by_name(game)?
-            .ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+        self.record(format!("Restore snapshot '{}' of {}", name, game), game, |agm| {
+            let data_dir = Config::get_data_dir()?;
+            // The snapshot's backups replace the current ones
+            if let Some(operation) = &mut agm.operation {
+                operation.track_tree(&data_dir.join("backups").join(game))?;
+            }
+            agm.purge_game(game)?;
 
-        let preset_path = Config::get_data_dir()?
-            .join("presets")
-            .join(game)
-            .join(format!("{}.yaml", active_preset_name));
+            let snapshot_dir = Snapshot::path(game, name)?;
+            let preset_dir = data_dir.join("presets").join(game);
+            if preset_dir.exists() {
+                fs::remove_dir_all(&preset_dir)?;
+            }
+            let mut presets = Vec::new();
+            for file in snapshot::copy_tree(&snapshot_dir.join(snapshot::PRESET_DIR), &preset_dir)? {
+                if file.extension().is_some_and(|e| e == "yaml")
+                    && let Some(preset) = file.file_stem().and_then(|s| s.to_str())
+                {
+                    presets.push(preset.to_string());
+                }
+            }
+            agm.config.modify(|config| {
+                let game_config = config.get_or_create_game(game);
+                game_config.presets = presets;
+                game_config.active_preset = None;
+            })?;
 
-        let preset = Preset::from_file(&preset_path);
+            let backup_root = data_dir.join("backups").join(game);
+            let roots = profile.deployment_roots();
+            for file in snapshot::copy_tree(&snapshot_dir.join(snapshot::BACKUP_DIR), &backup_root)? {
+                let mut components = file.components();
+                let Some(root_name) = components.next().and_then(|c| c.as_os_str().to_str()) else {
+                    continue;
+                };
+                let Some((_, root)) = roots.iter().find(|(name, _)| name == root_name) else {
+                    continue;
+                };
+                // The file the game has now makes way for the snapshot's, and
+                // stays in the trash for undo and `agm trash restore`
+                let dest = root.join(components.as_path());
+                if dest.is_file() && !dest.is_symlink() {
+                    agm.trash_game_file(game, &dest)?;
+                }
+            }
 
-        for mod_entry in &preset.mods {
-            let mod_name = match mod_entry {
-                preset::Mod::Simple(name) => name,
-                preset::Mod::Detailed(info) => &info.name,
+            let verification = match &snapshot.active_preset {
+                Some(preset) => Some(agm.switch_preset(game, preset, true)?),
+                None => None,
             };
+            snapshot.restore_files(game)?;
+            Ok(verification)
+        })
+    }
 
-            let mod_spec_path = Config::get_data_dir()?
-                .join("storage")
-                .join(game)
-                .join(mod_name)
-                .join(format!("{}.yaml", mod_name));
+    pub fn delete_snapshot(&self, game: &str, name: &str) -> Result<(), Error> {
+        let game = &*self.resolve_game(game);
+        if !Snapshot::file(game, name)?.exists() {
+            return Err(Error::SnapshotNotFound(name.to_string(), game.to_string()));
+        }
+        Ok(Snapshot::delete(game, name)?)
+    }
 
-            if !mod_spec_path.exists() {
-                continue;
+    /// Removes every AGM link from the game directory and puts back every file
+    /// that was backed up, leaving the game as it was before AGM touched it.
+    /// Returns the removed links and the restored files.
+    pub fn purge_game(&mut self, game: &str) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Error> {
+        let game = &*self.resolve_game(game);
+        self.record(format!("Purge the deployment of {}", game), game, |agm| {
+            let profile = agm.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+            let roots = profile.deployment_roots();
+            let storage_root = Config::get_data_dir()?.join("storage").join(game);
+            let backup_root = Config::get_data_dir()?.join("backups").join(game);
+
+            let mut removed = Vec::new();
+            let mut seen = HashSet::new();
+            for (_, root) in &roots {
+                for dest in verify::find_agm_links(root, &storage_root)? {
+                    // Nested roots find the same link twice
+                    if !seen.insert(dest.clone()) {
+                        continue;
+                    }
+                    crate::symlink::remove_symlink(&dest)?;
+                    removed.push(dest);
+                }
             }
+            deploy::remove_created_dirs(game)?;
 
-            let mod_spec: crate::mod_spec::ModSpec =
-            serde_yaml::from_str(&std::fs::read_to_string(&mod_spec_path)?)?;
+            let restored = verify::restore_backups(&roots, &backup_root)?;
+            Deployment::clear(game)?;
+            agm.write_load_order(&profile, game, None)?;
 
-            for file_entry in &mod_spec.files {
-            if file_entry.point.is_empty() {
-                continue;
-            }
+            agm.config.modify(|config| {
+                if let Some(game_config) = config.get_game_mut(game) {
+                    game_config.active_preset = None;
+                }
+            })?;
 
-            let dest_dir_suffix = match profile.resolve_point(&file_entry.point) {
-                Some(p) => p,
-                None => continue,
-            };
+            Ok((removed, restored))
+        })
+    }
 
-            let dest_path = Path::new(&profile.game.path)
-                .join(dest_dir_suffix)
-                .join(&file_entry.target);
+    /// Verifies the game directory and fixes everything that was found,
+    /// returning the issues as they were before the repair.
+    pub fn repair_game(&self, game: &str) -> Result<VerifyReport, Error> {
+        let game = &*self.resolve_game(game);
+        let profile = self.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+        let mut report = self.verify_game(game)?;
 
-            if !dest_path.exists() || !dest_path.is_symlink() {
-                continue;
-            }
+        let backup_root = Config::get_data_dir()?.join("backups").join(game);
+        report.kept = verify::repair(game, &report, &profile.deployment_roots(), &backup_root)?;
 
-            std::fs::remove_file(&dest_path)?;
-                removed_symlinks.push(dest_path);
-            }
-        }
+        // Links the repair skipped or could not create stay undeployed, so
+        // the next deployment creates them
+        let links = self
+            .expected_links(&profile, game)?
+            .into_iter()
+            .filter(|(source, dest)| fs::read_link(dest).is_ok_and(|target| &target == source))
+            .collect();
+        Deployment { links }.save(game)?;
 
-        Ok(removed_symlinks)
+        Ok(report)
     }
 
+    fn deactivate_preset(&mut self, game: &str) -> Result<Vec<PathBuf>, Error> {
+        let mut removed_symlinks = vec![];
 
-    fn activate_preset(&mut self, game: &str, preset_name: &str) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
-        let _profile = self.get_profile_by_name(game)?.ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+        let game_config = match self.config.get_game(game) {
+            Some(gc) => gc,
+            None => return Ok(removed_symlinks),
+        };
 
-        let preset_path = Config::get_data_dir()?.join("presets").join(game).join(format!("{}.yaml", preset_name));
-        if !preset_path.exists() {
-            return Err(Error::PresetNotFound(preset_name.to_string(), game.to_string()));
+        if game_config.active_preset.is_none() {
+            return Ok(removed_symlinks);
         }
-        let preset = Preset::from_file(&preset_path);
-        let mut created_symlinks = vec![];
 
-        for mod_entry in &preset.mods {
-            let mod_name = match mod_entry {
-                preset::Mod::Simple(name) => name,
-                preset::Mod::Detailed(info) => &info.name,
-            };
-            created_symlinks.extend(self.activate_mod(game, mod_name)?);
+        let profile = self
+            .get_profile_by_name(game)?
+            .ok_or_else(|| Error::ProfileNotFound(game.to_string()))?;
+
+        for (source_path, dest_path) in self.deployed_links(&profile, game)? {
+            let is_ours = fs::read_link(&dest_path).map(|target| target == source_path).unwrap_or(false);
+            if !is_ours {
+                continue;
+            }
+
+            crate::symlink::remove_symlink(&dest_path)?;
+            removed_symlinks.push(dest_path);
         }
-        Ok(created_symlinks)
+
+        Deployment::clear(game)?;
+        Ok(removed_symlinks)
     }
 }
 
-pub async fn run_url_handler() -> Result<(), Box<dyn std::error::Error + Send>> {
+/// Waits for a link from the URL handler and installs the file it points to
+/// into the profile of its game.
+pub async fn run_url_handler(reporter: &dyn InstallReporter) -> Result<(), Box<dyn std::error::Error + Send>> {
     let (url_sender, mut url_receiver) = create_url_channel();
     let port = 3000;
 
@@ -611,39 +2024,36 @@ pub async fn run_url_handler() -> Result<(), Box<dyn std::error::Error + Send>>
 
     if let Some(url_message) = url_receiver.recv().await {
         if let Ok(parsed_url) = Url::parse(&url_message.url) {
-            if parsed_url.scheme() == "nxm" {
-                let game = parsed_url.host_str().unwrap_or_default().to_string();
-                let path_segments: Vec<&str> =
-                    parsed_url.path_segments().map(|c| c.collect()).unwrap_or_default();
-
-                if path_segments.len() == 4
-                    && path_segments[0] == "mods"
-                    && path_segments[2] == "files"
-                {
-                    let mod_id: u64 = path_segments[1].parse().unwrap_or(0);
-                    let file_id: u64 = path_segments[3].parse().unwrap_or(0);
-
-                    if mod_id > 0 && file_id > 0 {
-                        let agm = match Agm::new() {
-                            Ok(agm) => agm,
-                            Err(e) => {
-                                eprintln!("Error initializing AGM: {}", e);
-                                return Err(Box::new(e));
-                            }
-                        };
-                        if let Some(api_key) = agm.get_nexus_api_key() {
-                            match nexus::get_download_link(api_key, &game, mod_id, file_id).await {
-                                Ok(link) => {
-                                    println!("{}", link);
-                                }
-                                Err(e) => {
-                                    eprintln!("Error getting download link: {}", e);
-                                }
+            if let Some((game, mod_id, file_id)) = nexus::parse_nxm(&parsed_url) {
+                let agm = match Agm::new() {
+                    Ok(agm) => agm,
+                    Err(e) => {
+                        eprintln!("Error initializing AGM: {}", e);
+                        return Err(Box::new(e));
+                    }
+                };
+                // Nexus names games by its own domain, which a profile takes
+                // on as an alias
+                let Some(profile) = agm.config.resolve_game(&game).map(str::to_string) else {
+                    eprintln!(
+                        "No profile for Nexus game '{}'. Add it with 'agm profile alias <profile> {}'",
+                        game, game
+                    );
+                    return Ok(());
+                };
+                if let Some(api_key) = agm.get_nexus_api_key() {
+                    match nexus::get_download_link(api_key, &game, mod_id, file_id).await {
+                        Ok(link) => {
+                            if let Err(e) = install_download(&agm, &link, &profile, reporter).await {
+                                eprintln!("Error installing into profile '{}': {}", profile, e);
                             }
-                        } else {
-                            eprintln!("Nexus API key not set. Please set it using 'agm config --nexus-api-key <key>'");
+                        }
+                        Err(e) => {
+                            eprintln!("Error getting download link: {}", e);
                         }
                     }
+                } else {
+                    eprintln!("Nexus API key not set. Please set it using 'agm config --nexus-api-key <key>'");
                 }
             }
         }
@@ -653,3 +2063,202 @@ pub async fn run_url_handler() -> Result<(), Box<dyn std::error::Error + Send>>
         .await
         .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?
 }
+
+/// Downloads `link` and installs the archive into `profile` as a mod named
+/// after it.
+async fn install_download(agm: &Agm, link: &str, profile: &str, reporter: &dyn InstallReporter) -> io::Result<()> {
+    let archive = download::download(link, None).await?;
+    let default_name = archive.file_stem().and_then(|s| s.to_str()).unwrap_or("unnamed_mod").to_string();
+    let mod_name = reporter.prompt_for_mod_name(&default_name)?;
+    agm.install_mods(&[archive.display().to_string()], profile, &mod_name, reporter).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    /// A game with the mods `a` and `b`, each providing one plugin and having
+    /// a preset of its own. Preset `a` is active.
+    struct Fixture {
+        game: PathBuf,
+        plugins: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(root: &Path) -> Self {
+            let data_dir = Config::get_data_dir().unwrap();
+            let fixture = Self {
+                game: root.join("game"),
+                plugins: root.join("plugins.txt"),
+            };
+
+            fs::create_dir_all(data_dir.join("profiles")).unwrap();
+            fs::write(
+                data_dir.join("profiles").join("game.yaml"),
+                format!(
+                    "game:\n  name: Game\n  path: {}\nload_order:\n  plugins:\n  - esp\n  files:\n  - path: {}\n    format: plugins\n",
+                    fixture.game.display(),
+                    fixture.plugins.display()
+                ),
+            )
+            .unwrap();
+
+            fs::create_dir_all(data_dir.join("presets").join("game")).unwrap();
+            for name in ["a", "b"] {
+                let storage = data_dir.join("storage").join("game").join(name);
+                fs::create_dir_all(&storage).unwrap();
+                fs::write(storage.join(format!("{}.esp", name)), "").unwrap();
+                fs::write(
+                    storage.join(format!("{}.yaml", name)),
+                    format!("name: {}\nfiles:\n- target: {}.esp\n  point: Data/\n", name, name),
+                )
+                .unwrap();
+                fs::write(
+                    data_dir.join("presets").join("game").join(format!("{}.yaml", name)),
+                    format!("name: {}\nmods:\n- {}\n", name, name),
+                )
+                .unwrap();
+            }
+            fs::create_dir_all(fixture.game.join("Data")).unwrap();
+
+            Config::update(|config| {
+                let game = config.get_or_create_game("game");
+                game.presets = vec!["a".to_string(), "b".to_string()];
+                game.active_preset = Some("a".to_string());
+            })
+            .unwrap();
+            fixture
+        }
+
+        /// The link of a mod's plugin.
+        fn link(&self, name: &str) -> deploy::Link {
+            let storage = Config::get_data_dir().unwrap().join("storage").join("game").join(name);
+            (storage.join(format!("{}.esp", name)), self.game.join("Data").join(format!("{}.esp", name)))
+        }
+    }
+
+    #[test]
+    fn failed_operation_can_be_undone() {
+        let (_guard, _) = test_env::isolated("record-failed");
+        let mut agm = Agm::new().unwrap();
+        let preset_dir = Config::get_data_dir().unwrap().join("presets").join("game");
+        fs::create_dir_all(&preset_dir).unwrap();
+
+        let result: Result<(), Error> = agm.record("Break".to_string(), "game", |_| {
+            fs::write(preset_dir.join("half.yaml"), "name: half\nmods: []\n")?;
+            Err(Error::NoActivePreset("game".to_string()))
+        });
+        assert!(result.is_err());
+
+        let history = History::load().unwrap();
+        assert_eq!(history.undoable().unwrap().description, "Break (failed)");
+        agm.undo().unwrap().unwrap();
+        assert!(!preset_dir.join("half.yaml").exists());
+    }
+
+    #[test]
+    fn undoing_a_snapshot_restore_puts_the_backups_back() {
+        let (_guard, root) = test_env::isolated("snapshot-undo");
+        Fixture::new(&root);
+        let backup_root = Config::get_data_dir().unwrap().join("backups").join("game");
+        let backup = backup_root.join("game").join("Data").join("old.esp");
+        fs::create_dir_all(backup.parent().unwrap()).unwrap();
+        fs::write(&backup, "from the snapshot").unwrap();
+
+        let mut agm = Agm::new().unwrap();
+        agm.create_snapshot("game", "snap").unwrap();
+        fs::remove_dir_all(&backup_root).unwrap();
+
+        agm.restore_snapshot("game", "snap").unwrap().unwrap().wait().unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "from the snapshot");
+
+        agm.undo().unwrap().unwrap();
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn repair_records_only_the_links_it_made() {
+        let (_guard, root) = test_env::isolated("repair-kept");
+        let fixture = Fixture::new(&root);
+        let a = fixture.link("a");
+        fs::write(&a.1, "the user's").unwrap();
+        let backup = Config::get_data_dir().unwrap().join("backups").join("game").join("game/Data/a.esp");
+        fs::create_dir_all(backup.parent().unwrap()).unwrap();
+        fs::write(&backup, "the original").unwrap();
+
+        let report = Agm::new().unwrap().repair_game("game").unwrap();
+        assert_eq!(report.kept, vec![a.1.clone()]);
+        assert_eq!(fs::read_to_string(&a.1).unwrap(), "the user's");
+        assert!(Deployment::load("game").unwrap().unwrap().links.is_empty());
+    }
+
+    #[test]
+    fn switch_interrupted_before_commit_is_undone() {
+        let (_guard, root) = test_env::isolated("recover-undo");
+        let fixture = Fixture::new(&root);
+        let (a, b) = (fixture.link("a"), fixture.link("b"));
+
+        // `a` was unlinked and `b` linked. Since then the user put a file in
+        // the way of a third link and replaced the parent of a fourth one
+        crate::symlink::create_symlink(&b.0, &b.1).unwrap();
+        let in_the_way = fixture.game.join("Data").join("user.esp");
+        fs::write(&in_the_way, "").unwrap();
+        fs::write(fixture.game.join("Textures"), "").unwrap();
+        let no_parent = fixture.game.join("Textures").join("sky.dds");
+        fs::write(&fixture.plugins, "*b.esp\n").unwrap();
+
+        let mut journal = SwitchJournal::begin("game", Some("a"), "b").unwrap();
+        journal.record(&Entry::Unlink { source: a.0.clone(), dest: a.1.clone() }).unwrap();
+        journal.record(&Entry::Unlink { source: root.join("user.esp"), dest: in_the_way.clone() }).unwrap();
+        journal.record(&Entry::Unlink { source: root.join("sky.dds"), dest: no_parent.clone() }).unwrap();
+        journal.record(&Entry::Link { source: b.0.clone(), dest: b.1.clone() }).unwrap();
+        drop(journal);
+
+        let agm = Agm::new().unwrap();
+        let [recovered] = agm.recovered_switches() else {
+            panic!("expected one recovered switch");
+        };
+        assert!(!recovered.committed);
+        assert_eq!(recovered.blocked, vec![no_parent, in_the_way]);
+        assert!(recovered.load_order_error.is_none());
+
+        assert_eq!(fs::read_link(&a.1).unwrap(), a.0);
+        assert!(!b.1.is_symlink());
+        let plugins = fs::read_to_string(&fixture.plugins).unwrap();
+        assert!(plugins.contains("*a.esp") && !plugins.contains("b.esp"));
+        assert_eq!(agm.config.get_game("game").unwrap().active_preset.as_deref(), Some("a"));
+        assert!(journal::pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn switch_interrupted_after_commit_is_finished() {
+        let (_guard, root) = test_env::isolated("recover-finish");
+        let fixture = Fixture::new(&root);
+        let (a, b) = (fixture.link("a"), fixture.link("b"));
+
+        crate::symlink::create_symlink(&b.0, &b.1).unwrap();
+        fs::write(&fixture.plugins, "*a.esp\n").unwrap();
+        Deployment { links: vec![a.clone()] }.save("game").unwrap();
+
+        let mut journal = SwitchJournal::begin("game", Some("a"), "b").unwrap();
+        journal.record(&Entry::Unlink { source: a.0.clone(), dest: a.1.clone() }).unwrap();
+        journal.record(&Entry::Link { source: b.0.clone(), dest: b.1.clone() }).unwrap();
+        journal.record(&Entry::Commit).unwrap();
+        drop(journal);
+
+        let agm = Agm::new().unwrap();
+        let [recovered] = agm.recovered_switches() else {
+            panic!("expected one recovered switch");
+        };
+        assert!(recovered.committed);
+        assert!(recovered.blocked.is_empty());
+
+        assert_eq!(fs::read_link(&b.1).unwrap(), b.0);
+        assert_eq!(Deployment::load("game").unwrap().unwrap().links, vec![b]);
+        let plugins = fs::read_to_string(&fixture.plugins).unwrap();
+        assert!(plugins.contains("*b.esp") && !plugins.contains("a.esp"));
+        assert_eq!(agm.config.get_game("game").unwrap().active_preset.as_deref(), Some("b"));
+        assert!(journal::pending().unwrap().is_empty());
+    }
+}
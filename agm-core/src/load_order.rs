@@ -0,0 +1,245 @@
+use crate::deploy::ModFiles;
+use crate::profile::Profile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How a game finds out which plugins to load, configured in its profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadOrder {
+    /// Extensions or file names of the mod files that are plugins, e.g. `esp`
+    /// or `About.xml`.
+    pub plugins: Vec<String>,
+    /// Read each plugin's id from this XML element in the plugin file instead
+    /// of using its file name, e.g. `packageId` for RimWorld.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_tag: Option<String>,
+    /// Plugins the game always loads first, in their fixed order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub implicit: Vec<String>,
+    /// Rule files for `agm preset sort`, e.g. a masterlist followed by the
+    /// user's own rules. Later files add to earlier ones. May use `${VAR}`
+    /// variables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+    /// The list files to write.
+    pub files: Vec<ListFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListFile {
+    /// Where the list lives. May use `${VAR}` variables.
+    pub path: String,
+    pub format: ListFormat,
+    /// The element holding the list in `xml` files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub element: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    /// `plugins.txt`: one plugin per line, active ones marked with `*`.
+    /// Implicit plugins are left out.
+    Plugins,
+    /// `loadorder.txt`: every plugin in order, implicit ones included.
+    Loadorder,
+    /// An XML list of `<li>` entries, like RimWorld's `ModsConfig.xml`.
+    Xml,
+}
+
+/// A plugin deployed by a mod.
+#[derive(Debug, Clone)]
+pub struct PluginFile {
+    /// The name the game's lists use for the plugin.
+    pub name: String,
+    /// The file in storage.
+    pub source: PathBuf,
+    /// Where the file is linked to.
+    pub dest: PathBuf,
+}
+
+impl LoadOrder {
+    fn is_plugin(&self, file_name: &str) -> bool {
+        let extension = Path::new(file_name).extension().and_then(|s| s.to_str());
+        self.plugins.iter().any(|plugin| {
+            plugin.eq_ignore_ascii_case(file_name)
+                || extension.is_some_and(|extension| plugin.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// The plugins a mod deploys, in the order of its spec. Files without a
+    /// placement are not deployed and so are not loaded either.
+    pub fn plugins_of(&self, mod_files: &ModFiles) -> io::Result<Vec<PluginFile>> {
+        let mut plugins = Vec::new();
+
+        for (target, base) in &mod_files.files {
+            let Some(base) = base else {
+                continue;
+            };
+            let Some(file_name) = target.file_name().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !self.is_plugin(file_name) {
+                continue;
+            }
+
+            let id = match &self.id_tag {
+                Some(tag) => {
+                    let text = fs::read_to_string(mod_files.storage.join(target))?;
+                    match xml_element(&text, tag) {
+                        Some(id) => id.to_string(),
+                        None => continue,
+                    }
+                }
+                None => file_name.to_string(),
+            };
+            plugins.push(PluginFile {
+                name: id,
+                source: mod_files.storage.join(target),
+                dest: base.join(target),
+            });
+        }
+        Ok(plugins)
+    }
+
+    /// Writes every list file. `active` is the load order of the plugins AGM
+    /// deploys and `managed` holds every plugin any installed mod provides.
+    /// Entries the user added by other means are kept ahead of AGM's.
+    pub fn write(&self, profile: &Profile, active: &[String], managed: &HashSet<String>) -> io::Result<()> {
+        let implicit: HashSet<String> = self.implicit.iter().map(|p| p.to_lowercase()).collect();
+
+        for list_file in &self.files {
+            let path = profile.expand(&list_file.path).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Could not expand the load order path '{}'", list_file.path),
+                )
+            })?;
+
+            let existing = if path.exists() { fs::read_to_string(&path)? } else { String::new() };
+            let keep = |entry: &str| {
+                let name = entry.trim_start_matches('*').to_lowercase();
+                !managed.contains(&name) && !implicit.contains(&name)
+            };
+
+            let content = match list_file.format {
+                ListFormat::Plugins => {
+                    let mut content = String::from("# This file is used by the game to keep track of your downloaded content.\n");
+                    for entry in text_entries(&existing).filter(|e| keep(e)) {
+                        content.push_str(entry);
+                        content.push('\n');
+                    }
+                    for plugin in active {
+                        content.push_str(&format!("*{}\n", plugin));
+                    }
+                    content
+                }
+                ListFormat::Loadorder => {
+                    let mut content = String::new();
+                    let unmanaged = text_entries(&existing).filter(|e| keep(e)).map(|e| e.trim_start_matches('*'));
+                    for plugin in self.implicit.iter().map(String::as_str).chain(unmanaged).chain(active.iter().map(String::as_str)) {
+                        content.push_str(plugin);
+                        content.push('\n');
+                    }
+                    content
+                }
+                ListFormat::Xml => {
+                    let element = list_file.element.as_deref().unwrap_or("activeMods");
+                    let unmanaged: Vec<&str> = xml_list_entries(&existing, element).into_iter().filter(|e| keep(e)).collect();
+                    let entries: Vec<&str> = self
+                        .implicit
+                        .iter()
+                        .map(String::as_str)
+                        .chain(unmanaged)
+                        .chain(active.iter().map(String::as_str))
+                        .collect();
+                    replace_xml_list(&existing, element, &entries).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{} has no <{}> element", path.display(), element),
+                        )
+                    })?
+                }
+            };
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, content)?;
+        }
+        Ok(())
+    }
+}
+
+/// Orders plugins by a preset's explicit load order. Listed plugins come
+/// first in the listed order, the rest follow in the order of the preset's
+/// mods. Duplicates keep their first position.
+pub fn order(plugins: Vec<String>, preferred: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut ordered = Vec::new();
+
+    for plugin in preferred {
+        if plugins.iter().any(|p| p.eq_ignore_ascii_case(plugin)) && seen.insert(plugin.to_lowercase()) {
+            ordered.push(plugin.clone());
+        }
+    }
+    for plugin in plugins {
+        if seen.insert(plugin.to_lowercase()) {
+            ordered.push(plugin);
+        }
+    }
+    ordered
+}
+
+fn text_entries(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// The trimmed text of the first `<tag>` element. Enough for the flat files
+/// games use, not a general XML parser.
+fn xml_element<'a>(text: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = text.find(&open)? + open.len();
+    let end = text[start..].find(&close)? + start;
+    Some(text[start..end].trim())
+}
+
+fn xml_list_entries<'a>(text: &'a str, element: &str) -> Vec<&'a str> {
+    let Some(list) = xml_element(text, element) else {
+        return Vec::new();
+    };
+
+    list.split("<li>")
+        .skip(1)
+        .filter_map(|item| item.split("</li>").next())
+        .map(str::trim)
+        .collect()
+}
+
+/// Replaces the entries of `<element>` in `text`. Without any text, a new
+/// RimWorld style document holding just the list is created.
+fn replace_xml_list(text: &str, element: &str, entries: &[&str]) -> Option<String> {
+    let mut list = String::from("\n");
+    for entry in entries {
+        list.push_str(&format!("    <li>{}</li>\n", entry));
+    }
+    list.push_str("  ");
+
+    if text.trim().is_empty() {
+        return Some(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ModsConfigData>\n  <{element}>{list}</{element}>\n</ModsConfigData>\n"
+        ));
+    }
+
+    let open = format!("<{}>", element);
+    let close = format!("</{}>", element);
+    let start = text.find(&open)? + open.len();
+    let end = text[start..].find(&close)? + start;
+    Some(format!("{}{}{}", &text[..start], list, &text[end..]))
+}
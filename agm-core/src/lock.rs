@@ -0,0 +1,254 @@
+use crate::config::Config;
+use crate::mod_spec::{FileEntry, ModSpec};
+use crate::validate::{load_yaml, LoadError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The SHA-256 of a file as lowercase hex.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The hash recorded for a mod installed from `archives`. A single archive is
+/// its own SHA-256, several are hashed as the list of their hashes in order.
+pub fn archive_hash(archives: &[PathBuf]) -> io::Result<String> {
+    if let [archive] = archives {
+        return sha256_file(archive);
+    }
+
+    let mut hasher = Sha256::new();
+    for archive in archives {
+        hasher.update(sha256_file(archive)?.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// A mod exactly as it was installed when the preset was locked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedMod {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Hash of the archive the mod was installed from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    pub files: Vec<FileEntry>,
+}
+
+impl LockedMod {
+    pub fn from_spec(spec: &ModSpec) -> Self {
+        Self {
+            name: spec.name.clone(),
+            version: spec.version.clone(),
+            url: spec.url.clone(),
+            sha256: spec.sha256.clone(),
+            files: spec.files.clone(),
+        }
+    }
+}
+
+/// The installed state of every mod of a preset, kept next to the preset as
+/// `<preset>.lock`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub preset: String,
+    pub mods: Vec<LockedMod>,
+}
+
+/// How an installed mod differs from its locked state.
+#[derive(Debug)]
+pub enum Difference {
+    NotInstalled { name: String },
+    Hash {
+        name: String,
+        locked: String,
+        installed: Option<String>,
+    },
+    Version {
+        name: String,
+        locked: Option<String>,
+        installed: Option<String>,
+    },
+    /// The files or their placements changed.
+    Files { name: String },
+    /// The mod is in the preset but not in the lockfile.
+    Unlocked { name: String },
+}
+
+impl Difference {
+    /// Whether the installed mod is not the archive that was locked, which
+    /// keeps the preset from being activated.
+    pub fn is_mismatch(&self) -> bool {
+        matches!(self, Difference::Hash { .. })
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::NotInstalled { name } => write!(f, "{} is locked but not installed", name),
+            Difference::Hash { name, locked, installed } => write!(
+                f,
+                "{} was installed from a different archive (locked {}, installed {})",
+                name,
+                locked,
+                installed.as_deref().unwrap_or("unknown")
+            ),
+            Difference::Version { name, locked, installed } => write!(
+                f,
+                "{} is at version {}, locked at {}",
+                name,
+                installed.as_deref().unwrap_or("unknown"),
+                locked.as_deref().unwrap_or("unknown")
+            ),
+            Difference::Files { name } => write!(f, "{} places its files differently than locked", name),
+            Difference::Unlocked { name } => write!(f, "{} is not in the lockfile", name),
+        }
+    }
+}
+
+impl Lockfile {
+    pub fn path(game: &str, preset: &str) -> io::Result<PathBuf> {
+        Ok(Config::get_data_dir()?.join("presets").join(game).join(format!("{}.lock", preset)))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        load_yaml(path)
+    }
+
+    pub fn save(&self, game: &str) -> io::Result<()> {
+        let yaml_string = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(Lockfile::path(game, &self.preset)?, yaml_string)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedMod> {
+        self.mods.iter().find(|m| m.name == name)
+    }
+
+    /// Compares the lock with the installed mods of the preset. `installed`
+    /// holds every enabled mod of the preset with its spec, if it has one.
+    pub fn compare(&self, installed: &[(String, Option<ModSpec>)]) -> Vec<Difference> {
+        let mut differences = Vec::new();
+
+        for (name, spec) in installed {
+            let Some(locked) = self.get(name) else {
+                differences.push(Difference::Unlocked { name: name.clone() });
+                continue;
+            };
+            let Some(spec) = spec else {
+                differences.push(Difference::NotInstalled { name: name.clone() });
+                continue;
+            };
+
+            if let Some(hash) = &locked.sha256
+                && spec.sha256.as_ref() != Some(hash)
+            {
+                differences.push(Difference::Hash {
+                    name: name.clone(),
+                    locked: hash.clone(),
+                    installed: spec.sha256.clone(),
+                });
+            }
+            if locked.version != spec.version {
+                differences.push(Difference::Version {
+                    name: name.clone(),
+                    locked: locked.version.clone(),
+                    installed: spec.version.clone(),
+                });
+            }
+            if locked.files != spec.files {
+                differences.push(Difference::Files { name: name.clone() });
+            }
+        }
+        differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(target: &str) -> FileEntry {
+        FileEntry {
+            target: target.to_string(),
+            point: "Data/".to_string(),
+        }
+    }
+
+    fn spec(version: &str, sha256: &str, files: &[&str]) -> ModSpec {
+        ModSpec {
+            name: "a".to_string(),
+            url: None,
+            version: Some(version.to_string()),
+            requires: Vec::new(),
+            optional: Vec::new(),
+            conflicts: Vec::new(),
+            sha256: Some(sha256.to_string()),
+            files: files.iter().map(|target| file(target)).collect(),
+        }
+    }
+
+    fn lockfile() -> Lockfile {
+        Lockfile {
+            preset: "test".to_string(),
+            mods: vec![LockedMod::from_spec(&spec("1.0", "abc", &["a.esp"]))],
+        }
+    }
+
+    fn compare(spec: Option<ModSpec>) -> Vec<Difference> {
+        lockfile().compare(&[("a".to_string(), spec)])
+    }
+
+    #[test]
+    fn matching_mod_has_no_differences() {
+        assert!(compare(Some(spec("1.0", "abc", &["a.esp"]))).is_empty());
+    }
+
+    #[test]
+    fn different_archive_is_a_mismatch() {
+        let differences = compare(Some(spec("1.0", "def", &["a.esp"])));
+        assert!(matches!(
+            differences.as_slice(),
+            [Difference::Hash { locked, installed: Some(installed), .. }] if locked == "abc" && installed == "def"
+        ));
+        assert!(differences[0].is_mismatch());
+    }
+
+    #[test]
+    fn different_version_is_reported() {
+        let differences = compare(Some(spec("1.1", "abc", &["a.esp"])));
+        assert!(matches!(
+            differences.as_slice(),
+            [Difference::Version { locked: Some(locked), installed: Some(installed), .. }]
+                if locked == "1.0" && installed == "1.1"
+        ));
+        assert!(!differences[0].is_mismatch());
+    }
+
+    #[test]
+    fn different_files_are_reported() {
+        let differences = compare(Some(spec("1.0", "abc", &["a.esp", "a.bsa"])));
+        assert!(matches!(differences.as_slice(), [Difference::Files { name }] if name == "a"));
+    }
+
+    #[test]
+    fn missing_mod_is_not_installed() {
+        let differences = compare(None);
+        assert!(matches!(differences.as_slice(), [Difference::NotInstalled { name }] if name == "a"));
+    }
+
+    #[test]
+    fn mod_outside_the_lock_is_unlocked() {
+        let differences = lockfile().compare(&[("b".to_string(), Some(spec("1.0", "abc", &[])))]);
+        assert!(matches!(differences.as_slice(), [Difference::Unlocked { name }] if name == "b"));
+    }
+}
@@ -1,13 +1,28 @@
+use crate::deps::Dependency;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModSpec {
     pub name: String,
     pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Mods that have to be in the same preset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<Dependency>,
+    /// Mods that may be left out, but have to match their constraint if used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub optional: Vec<Dependency>,
+    /// Mods that must not be in the same preset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Dependency>,
+    /// SHA-256 of the archive the mod was installed from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
     pub files: Vec<FileEntry>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileEntry {
     pub target: String,
     pub point: String,
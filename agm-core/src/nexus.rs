@@ -1,12 +1,46 @@
 #![allow(unused_imports)]
 use tokio::{fs::File, io::AsyncWriteExt};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
 struct DownloadLink {
     uri: String,
 }
 
+/// A file on Nexus Mods, as given in a preset entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NexusFile {
+    /// The game's domain name on Nexus, e.g. `skyrimspecialedition`.
+    pub game: String,
+    pub mod_id: u64,
+    pub file_id: u64,
+}
+
+impl NexusFile {
+    /// The `nxm://` link for the file, which downloading resolves through the
+    /// Nexus API.
+    pub fn to_nxm(&self) -> String {
+        format!("nxm://{}/mods/{}/files/{}", self.game, self.mod_id, self.file_id)
+    }
+}
+
+/// Splits an `nxm://<game>/mods/<mod id>/files/<file id>` link into its parts.
+pub fn parse_nxm(url: &url::Url) -> Option<(String, u64, u64)> {
+    if url.scheme() != "nxm" {
+        return None;
+    }
+
+    let game = url.host_str()?.to_string();
+    let path_segments: Vec<&str> = url.path_segments()?.collect();
+    if path_segments.len() != 4 || path_segments[0] != "mods" || path_segments[2] != "files" {
+        return None;
+    }
+
+    let mod_id: u64 = path_segments[1].parse().ok()?;
+    let file_id: u64 = path_segments[3].parse().ok()?;
+    (mod_id > 0 && file_id > 0).then_some((game, mod_id, file_id))
+}
+
 pub async fn get_download_link(
     api_key: &str,
     game: &str,
@@ -1,15 +1,79 @@
 use serde::{Deserialize, Serialize};
 use serde_yaml;
-use std::fs;
-use std::path::Path;
+use crate::deps::Dependency;
+use crate::nexus::NexusFile;
+use crate::validate::{load_yaml, LoadError};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Preset {
     pub name: String,
+    /// Presets of the same game whose mods come before this preset's own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Include>,
     pub mods: Vec<Mod>,
+    /// Plugins to load first, in this order. Plugins not listed follow in the
+    /// order of `mods`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_order: Option<Vec<String>>,
 }
 
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Include {
+    Simple(String),
+    Detailed {
+        name: String,
+        /// Mods of the included preset to leave out.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        exclude: Vec<String>,
+    },
+}
+
+impl Include {
+    pub fn name(&self) -> &str {
+        match self {
+            Include::Simple(name) => name,
+            Include::Detailed { name, .. } => name,
+        }
+    }
+
+    fn excludes(&self, mod_name: &str) -> bool {
+        match self {
+            Include::Simple(_) => false,
+            Include::Detailed { exclude, .. } => exclude.iter().any(|e| e == mod_name),
+        }
+    }
+
+    /// Leaves `mod_name` out of what this include brings in.
+    pub fn exclude(&mut self, mod_name: &str) {
+        match self {
+            Include::Simple(name) => {
+                *self = Include::Detailed {
+                    name: name.clone(),
+                    exclude: vec![mod_name.to_string()],
+                };
+            }
+            Include::Detailed { exclude, .. } => {
+                if !exclude.iter().any(|e| e == mod_name) {
+                    exclude.push(mod_name.to_string());
+                }
+            }
+        }
+    }
+
+    /// Brings `mod_name` back in if this include left it out.
+    pub fn unexclude(&mut self, mod_name: &str) {
+        if let Include::Detailed { name, exclude } = self {
+            exclude.retain(|e| e != mod_name);
+            if exclude.is_empty() {
+                *self = Include::Simple(name.clone());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Mod {
@@ -17,11 +81,81 @@ pub enum Mod {
     Detailed(ModInfo),
 }
 
+impl Mod {
+    pub fn name(&self) -> &str {
+        match self {
+            Mod::Simple(name) => name,
+            Mod::Detailed(info) => &info.name,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        match self {
+            Mod::Simple(_) => true,
+            Mod::Detailed(info) => info.enabled,
+        }
+    }
+
+    /// Turns the mod on or off, keeping its place in the preset. An entry
+    /// left with nothing but its name is written as a plain name again.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        match self {
+            Mod::Simple(name) if !enabled => {
+                *self = Mod::Detailed(ModInfo {
+                    name: name.clone(),
+                    enabled,
+                    url: None,
+                    nexus: None,
+                    files: None,
+                    requires: Vec::new(),
+                    optional: Vec::new(),
+                    conflicts: Vec::new(),
+                });
+            }
+            Mod::Simple(_) => {}
+            Mod::Detailed(info) => {
+                info.enabled = enabled;
+                if enabled
+                    && info.url.is_none()
+                    && info.nexus.is_none()
+                    && info.files.is_none()
+                    && info.requires.is_empty()
+                    && info.optional.is_empty()
+                    && info.conflicts.is_empty()
+                {
+                    *self = Mod::Simple(info.name.clone());
+                }
+            }
+        }
+    }
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ModInfo {
     pub name: String,
+    /// A disabled mod keeps its place in the preset but is not deployed.
+    #[serde(default = "enabled_by_default", skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
     pub url: Option<String>,
+    /// Where to download the mod on Nexus Mods, if it has no `url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nexus: Option<NexusFile>,
     pub files: Option<Vec<String>>,
+    /// Added to the declarations of the mod's spec.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<Dependency>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub optional: Vec<Dependency>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Dependency>,
 }
 
 
@@ -29,20 +163,171 @@ impl Preset {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            include: Vec::new(),
             mods: Vec::<Mod>::new(),
+            load_order: None,
         }
     }
 
-    pub fn from_file(path: &Path) -> Self {
-        let raw_file_string =
-            fs::read_to_string(path).expect(&format!("Could not read file at {:?}", path));
-        let preset: Preset = serde_yaml::from_str(&raw_file_string)
-            .expect(&format!("Could not parse preset {:?}", path));
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        load_yaml(path)
+    }
+
+    /// Loads a preset with the mods of everything it includes merged in.
+    /// Included presets are looked up next to `path`.
+    pub fn from_file_flattened(path: &Path) -> Result<Self, LoadError> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::from_file(path)?.flatten(path, &mut vec![name])
+    }
+
+    /// The file of a preset followed by the files of every preset it
+    /// includes, directly or through other includes, each listed once.
+    pub fn include_files(path: &Path) -> Result<Vec<PathBuf>, LoadError> {
+        // Flattening reports unknown includes and cycles
+        Self::from_file_flattened(path)?;
+
+        let mut files = vec![path.to_path_buf()];
+        let mut i = 0;
+        while i < files.len() {
+            let preset_dir = files[i].parent().unwrap_or(Path::new(".")).to_path_buf();
+            for include in Self::from_file(&files[i])?.include {
+                let include_path = preset_dir.join(format!("{}.yaml", include.name()));
+                if !files.contains(&include_path) {
+                    files.push(include_path);
+                }
+            }
+            i += 1;
+        }
+        Ok(files)
+    }
+
+    /// Replaces the includes by their mods. Included mods come first, in the
+    /// order of `include`, then this preset's own. A mod listed more than once
+    /// keeps its first position and takes its last entry, so the including
+    /// preset can override how an included mod is set up.
+    fn flatten(self, path: &Path, seen: &mut Vec<String>) -> Result<Self, LoadError> {
+        let preset_dir = path.parent().unwrap_or(Path::new("."));
+        let mut mods: Vec<Mod> = Vec::new();
+        let mut load_order = self.load_order.unwrap_or_default();
+
+        let add = |mods: &mut Vec<Mod>, mod_entry: Mod| {
+            match mods.iter_mut().find(|m| m.name() == mod_entry.name()) {
+                Some(existing) => *existing = mod_entry,
+                None => mods.push(mod_entry),
+            }
+        };
+
+        for include in &self.include {
+            let include_name = include.name().to_string();
+            if seen.contains(&include_name) {
+                seen.push(include_name);
+                return Err(LoadError::IncludeCycle(seen.clone()));
+            }
+
+            let include_path = preset_dir.join(format!("{}.yaml", include_name));
+            if !include_path.exists() {
+                return Err(LoadError::UnknownInclude {
+                    path: path.to_path_buf(),
+                    include: include_name,
+                });
+            }
+
+            seen.push(include_name);
+            let included = Self::from_file(&include_path)?.flatten(&include_path, seen)?;
+            seen.pop();
+
+            for mod_entry in included.mods {
+                if !include.excludes(mod_entry.name()) {
+                    add(&mut mods, mod_entry);
+                }
+            }
+            for plugin in included.load_order.unwrap_or_default() {
+                if !load_order.contains(&plugin) {
+                    load_order.push(plugin);
+                }
+            }
+        }
+
+        for mod_entry in self.mods {
+            add(&mut mods, mod_entry);
+        }
 
-        preset
+        Ok(Preset {
+            name: self.name,
+            include: Vec::new(),
+            mods,
+            load_order: (!load_order.is_empty()).then_some(load_order),
+        })
     }
 
     pub fn to_yaml(&self) -> String {
         serde_yaml::to_string(self).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("agm-preset-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn names(preset: &Preset) -> Vec<&str> {
+        preset.mods.iter().map(Mod::name).collect()
+    }
+
+    #[test]
+    fn includes_are_flattened_without_excluded_mods() {
+        let dir = fixture_dir("chain");
+        fs::write(dir.join("base.yaml"), "name: base\nmods:\n- skse\n- ui fix\n- old patch\n").unwrap();
+        fs::write(
+            dir.join("graphics.yaml"),
+            "name: graphics\ninclude:\n- name: base\n  exclude:\n  - old patch\nmods:\n- textures\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("top.yaml"),
+            "name: top\ninclude:\n- graphics\nmods:\n- weather\n- name: skse\n  enabled: false\n",
+        )
+        .unwrap();
+
+        let preset = Preset::from_file_flattened(&dir.join("top.yaml")).unwrap();
+        assert_eq!(names(&preset), vec!["skse", "ui fix", "textures", "weather"]);
+        assert!(preset.include.is_empty());
+        // The including preset's entry overrides the included one in place
+        assert!(!preset.mods[0].enabled());
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let dir = fixture_dir("cycle");
+        fs::write(dir.join("a.yaml"), "name: a\ninclude:\n- b\nmods: []\n").unwrap();
+        fs::write(dir.join("b.yaml"), "name: b\ninclude:\n- a\nmods: []\n").unwrap();
+
+        match Preset::from_file_flattened(&dir.join("a.yaml")) {
+            Err(LoadError::IncludeCycle(cycle)) => assert_eq!(cycle, vec!["a", "b", "a"]),
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unexcluding_the_last_mod_gives_a_plain_include() {
+        let mut include = Include::Simple("base".to_string());
+        include.exclude("a");
+        include.exclude("b");
+        include.unexclude("a");
+        assert!(matches!(&include, Include::Detailed { exclude, .. } if exclude == &["b"]));
+        include.unexclude("b");
+        assert!(matches!(include, Include::Simple(name) if name == "base"));
+    }
+}
@@ -1,18 +1,41 @@
+use crate::load_order::LoadOrder;
+use crate::steam::SteamGame;
+use crate::templates;
+use crate::validate::{load_yaml, LoadError};
 use serde::{Deserialize, Serialize};
 use serde_yaml;
-use std::fs;
+use std::collections::BTreeMap;
+use std::env;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Profile {
+    /// A profile or bundled template this one builds on. Its roots and layout
+    /// are merged in, with this profile's values taking precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub game: Game,
+    /// Extra places mods can be deployed to besides the game directory, e.g.
+    /// `docs: ${PROTON_PREFIX}/drive_c/users/steamuser/Documents/My Games/X`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roots: BTreeMap<String, String>,
+    #[serde(default)]
     pub layout: Vec<Layout>,
+    /// The plugin list files the game reads, if it has any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_order: Option<LoadOrder>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Game {
     pub name: String,
     pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steam_library: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proton_prefix: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,6 +43,10 @@ pub struct Layout {
     pub name: String,
     #[serde(rename = "type")]
     pub node_type: LayoutType,
+    /// Root this node and its children live in. Defaults to the parent's
+    /// root, which is the game directory at the top level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
     pub sub: Option<Vec<Layout>>,
     pub mime: Option<Vec<String>>,
 }
@@ -34,30 +61,111 @@ pub enum LayoutType {
 impl Profile {
     pub fn new(name: &str, path: &str) -> Self {
         Self {
+            extends: None,
             game: Game {
                 name: name.to_string(),
                 path: path.to_string(),
+                appid: None,
+                steam_library: None,
+                proton_prefix: None,
             },
+            roots: BTreeMap::new(),
             layout: vec![
                 Layout {
                     name: "mods".to_string(),
                     node_type: LayoutType::Moddir,
+                    root: None,
                     sub: None,
                     mime: Some(vec!["txt".to_string(), "zip".to_string(), "rar".to_string(), "7z".to_string(), "tar".to_string()]),
                 },
             ],
+            load_order: None,
         }
     }
 
-    pub fn from_file(path: &Path) -> Self {
-        let raw_file_string =
-            fs::read_to_string(path).expect(&format!("Could not read file at {:?}", path));
-        let profile: Profile = serde_yaml::from_str(&raw_file_string)
-            .expect(&format!("Could not parse profile {:?}", path));
-
+    /// A default profile pointing at a game found in a Steam library.
+    pub fn from_steam(name: &str, steam_game: &SteamGame) -> Self {
+        let mut profile = Self::new(name, "");
+        profile.set_steam_game(steam_game);
         profile
     }
 
+    /// Points the profile at a game found in a Steam library.
+    pub fn set_steam_game(&mut self, steam_game: &SteamGame) {
+        self.game.path = steam_game.path.display().to_string();
+        self.game.appid = Some(steam_game.appid);
+        self.game.steam_library = Some(steam_game.library.display().to_string());
+        self.game.proton_prefix = steam_game
+            .proton_prefix
+            .as_ref()
+            .map(|prefix| prefix.display().to_string());
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        load_yaml(path)
+    }
+
+    /// Loads a profile with everything it extends merged in. Parents are
+    /// looked up as profiles in `profile_dir` first, then as bundled templates.
+    pub fn from_file_resolved(path: &Path, profile_dir: &Path) -> Result<Self, LoadError> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::from_file(path)?.resolve_extends(path, profile_dir, &mut vec![name])
+    }
+
+    fn resolve_extends(self, path: &Path, profile_dir: &Path, seen: &mut Vec<String>) -> Result<Self, LoadError> {
+        let Some(parent_name) = self.extends.clone() else {
+            return Ok(self);
+        };
+
+        if seen.contains(&parent_name) {
+            seen.push(parent_name);
+            return Err(LoadError::ExtendsCycle(seen.clone()));
+        }
+        seen.push(parent_name.clone());
+
+        let parent_path = profile_dir.join(format!("{}.yaml", parent_name));
+        let parent = if parent_path.exists() {
+            Self::from_file(&parent_path)?.resolve_extends(&parent_path, profile_dir, seen)?
+        } else if let Some(template) = templates::get(&parent_name) {
+            let template_path = PathBuf::from(format!("templates/{}.yaml", template.id));
+            let profile = template.profile().map_err(|source| LoadError::parse(&template_path, source))?;
+            profile.resolve_extends(&template_path, profile_dir, seen)?
+        } else {
+            return Err(LoadError::UnknownParent {
+                path: path.to_path_buf(),
+                parent: parent_name,
+            });
+        };
+
+        Ok(parent.merge(self))
+    }
+
+    /// Layers `child` on top of this profile. Layout nodes with the same name
+    /// are merged, and the child's type, root and mime rules replace the
+    /// parent's where given.
+    fn merge(mut self, child: Profile) -> Profile {
+        self.roots.extend(child.roots);
+        merge_layouts(&mut self.layout, child.layout);
+
+        let game = Game {
+            appid: child.game.appid.or(self.game.appid),
+            steam_library: child.game.steam_library.or(self.game.steam_library),
+            proton_prefix: child.game.proton_prefix.or(self.game.proton_prefix),
+            ..child.game
+        };
+
+        Profile {
+            extends: None,
+            game,
+            roots: self.roots,
+            layout: self.layout,
+            load_order: child.load_order.or(self.load_order),
+        }
+    }
+
     pub fn to_yaml(&self) -> String {
         serde_yaml::to_string(self).unwrap()
     }
@@ -79,39 +187,159 @@ impl Profile {
         }
     }
 
+    /// Resolves a point to the directory files placed there are linked into.
+    /// `@name` points at a moddir, anything else is a path that may use
+    /// `${VAR}` variables and is taken relative to the game directory.
     pub fn resolve_point(&self, point: &str) -> Option<PathBuf> {
-        if point.starts_with('@') {
-            let moddir_name = &point[1..];
-            let mut path = PathBuf::new();
-            self.find_moddir_path(&self.layout, moddir_name, &mut path)
+        if let Some(moddir_name) = point.strip_prefix('@') {
+            self.find_moddir_path(&self.layout, moddir_name, &self.game_path())
         } else {
-            Some(PathBuf::from(point))
+            Some(self.game_path().join(self.expand(point)?))
         }
     }
 
-    fn find_moddir_path(&self, layouts: &[Layout], moddir_name: &str, current_path: &mut PathBuf) -> Option<PathBuf> {
+    fn find_moddir_path(&self, layouts: &[Layout], moddir_name: &str, base: &Path) -> Option<PathBuf> {
         for layout in layouts {
-            let original_path = current_path.clone();
-            current_path.push(&layout.name);
+            let node_base = match &layout.root {
+                Some(root) => match self.root_path(root) {
+                    Some(root_path) => root_path,
+                    None => continue,
+                },
+                None => base.to_path_buf(),
+            };
+            let path = node_base.join(&layout.name);
 
             if layout.name == moddir_name {
                 if let LayoutType::Moddir = layout.node_type {
-                    return Some(current_path.clone());
+                    return Some(path);
                 }
             }
 
             if let Some(sub_layouts) = &layout.sub {
-                if let Some(found_path) = self.find_moddir_path(sub_layouts, moddir_name, current_path) {
+                if let Some(found_path) = self.find_moddir_path(sub_layouts, moddir_name, &path) {
                     return Some(found_path);
                 }
             }
-            *current_path = original_path; // backtrack
         }
         None
     }
+
+    /// The game directory with its variables expanded.
+    pub fn game_path(&self) -> PathBuf {
+        self.expand(&self.game.path)
+            .unwrap_or_else(|| PathBuf::from(&self.game.path))
+    }
+
+    /// Resolves a named root. `game` is the game directory unless the profile
+    /// defines a root with that name.
+    pub fn root_path(&self, name: &str) -> Option<PathBuf> {
+        match self.roots.get(name) {
+            Some(root) => self.expand(root),
+            None if name == "game" => Some(self.game_path()),
+            None => None,
+        }
+    }
+
+    /// Every root mods can be deployed into, starting with the game directory.
+    pub fn deployment_roots(&self) -> Vec<(String, PathBuf)> {
+        let mut roots = vec![("game".to_string(), self.game_path())];
+        for name in self.roots.keys() {
+            if name == "game" {
+                continue;
+            }
+            if let Some(path) = self.root_path(name) {
+                roots.push((name.clone(), path));
+            }
+        }
+        roots
+    }
+
+    /// Expands `${NAME}` variables in a path. Names are looked up in the
+    /// profile's roots, then `HOME`, `GAME`, `STEAM_LIBRARY` and
+    /// `PROTON_PREFIX`, then the environment. Returns `None` if any variable
+    /// is unknown.
+    pub fn expand(&self, value: &str) -> Option<PathBuf> {
+        self.expand_with(value, &mut Vec::new()).map(PathBuf::from)
+    }
+
+    fn expand_with(&self, value: &str, seen: &mut Vec<String>) -> Option<String> {
+        let mut expanded = String::new();
+        let mut rest = value;
+
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..].find('}')? + start;
+            let name = &rest[start + 2..end];
+
+            expanded.push_str(&rest[..start]);
+            expanded.push_str(&self.variable(name, seen)?);
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        Some(expanded)
+    }
+
+    fn variable(&self, name: &str, seen: &mut Vec<String>) -> Option<String> {
+        // Roots can refer to each other, but not in a cycle
+        if seen.iter().any(|s| s == name) {
+            return None;
+        }
+        seen.push(name.to_string());
+
+        let value = if let Some(root) = self.roots.get(name) {
+            self.expand_with(root, seen)
+        } else {
+            match name {
+                "HOME" => dirs_next::home_dir().map(|p| p.display().to_string()),
+                "GAME" => self.expand_with(&self.game.path, seen),
+                "STEAM_LIBRARY" => match &self.game.steam_library {
+                    Some(library) => self.expand_with(library, seen),
+                    None => env::var(name).ok(),
+                },
+                "PROTON_PREFIX" => match &self.game.proton_prefix {
+                    Some(prefix) => self.expand_with(prefix, seen),
+                    None => env::var(name).ok().or_else(|| {
+                        env::var("STEAM_COMPAT_DATA_PATH")
+                            .ok()
+                            .map(|p| Path::new(&p).join("pfx").display().to_string())
+                    }),
+                },
+                _ => env::var(name).ok(),
+            }
+        };
+
+        seen.pop();
+        value
+    }
+}
+
+fn merge_layouts(layouts: &mut Vec<Layout>, overrides: Vec<Layout>) {
+    for layout in overrides {
+        match layouts.iter_mut().find(|existing| existing.name == layout.name) {
+            Some(existing) => existing.merge(layout),
+            None => layouts.push(layout),
+        }
+    }
 }
 
 impl Layout {
+    fn merge(&mut self, other: Layout) {
+        self.node_type = other.node_type;
+        if other.root.is_some() {
+            self.root = other.root;
+        }
+        if other.mime.is_some() {
+            self.mime = other.mime;
+        }
+
+        self.sub = match (self.sub.take(), other.sub) {
+            (Some(mut sub), Some(other_sub)) => {
+                merge_layouts(&mut sub, other_sub);
+                Some(sub)
+            }
+            (sub, other_sub) => other_sub.or(sub),
+        };
+    }
+
     pub fn find_matching_moddir_point(&self, file_extension: &str) -> Option<String> {
         if let LayoutType::Moddir = self.node_type {
             if let Some(mimes) = &self.mime {
@@ -0,0 +1,177 @@
+use crate::config::Config;
+use crate::lock::LockedMod;
+use crate::validate::{is_plain_name, load_yaml, LoadError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const SNAPSHOT_FILE: &str = "snapshot.yaml";
+pub const PRESET_DIR: &str = "presets";
+pub const BACKUP_DIR: &str = "backups";
+const FILE_DIR: &str = "files";
+
+/// A file outside the data dir that AGM writes, like a plugin list, as it
+/// was when the snapshot was taken.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedFile {
+    pub path: PathBuf,
+    /// Name of the copy in the snapshot, `None` if the file did not exist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copy: Option<String>,
+}
+
+/// Everything AGM controls for a game at one point in time, kept in
+/// `snapshots/<game>/<name>/` with copies of the presets, lockfiles and
+/// backups.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_preset: Option<String>,
+    /// How every enabled mod of the active preset was installed.
+    #[serde(default)]
+    pub mods: Vec<LockedMod>,
+    #[serde(default)]
+    pub files: Vec<SavedFile>,
+}
+
+pub fn get_snapshot_dir(game: &str) -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("snapshots").join(game))
+}
+
+impl Snapshot {
+    /// The snapshot's directory. A name that is not a plain file name is an
+    /// error, so no snapshot lies outside `snapshots/<game>`.
+    pub fn path(game: &str, name: &str) -> io::Result<PathBuf> {
+        if !is_plain_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a valid snapshot name", name),
+            ));
+        }
+        Ok(get_snapshot_dir(game)?.join(name))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        load_yaml(path)
+    }
+
+    /// The snapshot's description file, which exists once it is saved.
+    pub fn file(game: &str, name: &str) -> io::Result<PathBuf> {
+        Ok(Self::path(game, name)?.join(SNAPSHOT_FILE))
+    }
+
+    /// The names of every saved snapshot of a game.
+    pub fn names(game: &str) -> io::Result<Vec<String>> {
+        let snapshot_dir = get_snapshot_dir(game)?;
+        if !snapshot_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(snapshot_dir)? {
+            let entry = entry?;
+            if !entry.path().join(SNAPSHOT_FILE).exists() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Writes the snapshot, copying `files` into it. The presets and backups
+    /// are expected to be copied in already.
+    pub fn save(&mut self, game: &str, files: &[PathBuf]) -> io::Result<()> {
+        let dir = Self::path(game, &self.name)?;
+        fs::create_dir_all(dir.join(FILE_DIR))?;
+
+        self.files.clear();
+        for (i, path) in files.iter().enumerate() {
+            let copy = if path.is_file() {
+                let copy = i.to_string();
+                fs::copy(path, dir.join(FILE_DIR).join(&copy))?;
+                Some(copy)
+            } else {
+                None
+            };
+            self.files.push(SavedFile { path: path.clone(), copy });
+        }
+
+        let yaml_string = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(dir.join(SNAPSHOT_FILE), yaml_string)
+    }
+
+    /// Puts the saved files back where they were.
+    pub fn restore_files(&self, game: &str) -> io::Result<()> {
+        let dir = Self::path(game, &self.name)?.join(FILE_DIR);
+        for file in &self.files {
+            match &file.copy {
+                Some(copy) => {
+                    if let Some(parent) = file.path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(dir.join(copy), &file.path)?;
+                }
+                None => {
+                    if file.path.is_file() {
+                        fs::remove_file(&file.path)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete(game: &str, name: &str) -> io::Result<()> {
+        let dir = Self::path(game, name)?;
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the files in `from` and below into `to`, returning their paths
+/// relative to `from`.
+pub fn copy_tree(from: &Path, to: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut copied = Vec::new();
+    if !from.exists() {
+        return Ok(copied);
+    }
+
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(from).map_err(io::Error::other)?;
+        let dest = to.join(relative);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(entry.path(), &dest)?;
+        copied.push(relative.to_path_buf());
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_that_leave_the_snapshot_dir_are_rejected() {
+        for name in ["..", "a/../../x", "/tmp/x", "a/b", ""] {
+            let error = Snapshot::path("game", name).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+        }
+        assert!(Snapshot::path("game", "before-update").unwrap().ends_with("game/before-update"));
+    }
+}
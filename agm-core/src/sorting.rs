@@ -0,0 +1,440 @@
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+const MASTER_FLAG: u32 = 0x1;
+/// The largest header read. Real ones stay far below this, even with a long
+/// list of overridden records.
+const MAX_HEADER_SIZE: usize = 16 * 1024 * 1024;
+
+/// What the header of a Bethesda plugin says about its place in the load order.
+#[derive(Debug, Default)]
+pub struct PluginHeader {
+    /// The plugin is flagged as a master and loads before all other plugins.
+    pub is_master: bool,
+    /// Plugins that have to be loaded before this one.
+    pub masters: Vec<String>,
+}
+
+/// Reads the `TES4` record at the start of a plugin. Returns `None` for files
+/// that are not Bethesda plugins.
+pub fn read_header(path: &Path) -> io::Result<Option<PluginHeader>> {
+    let mut file = File::open(path)?;
+
+    let mut record = [0u8; 24];
+    if file.read_exact(&mut record).is_err() || &record[0..4] != b"TES4" {
+        return Ok(None);
+    }
+
+    let data_size = u32::from_le_bytes([record[4], record[5], record[6], record[7]]) as usize;
+    let flags = u32::from_le_bytes([record[8], record[9], record[10], record[11]]);
+
+    if data_size > MAX_HEADER_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: header of {} bytes is too large", path.display(), data_size),
+        ));
+    }
+
+    // Oblivion's record header is 20 bytes, later games use 24
+    let oblivion = &record[20..24] == b"HEDR";
+    if oblivion && data_size < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: header of {} bytes is too small", path.display(), data_size),
+        ));
+    }
+
+    let mut data = vec![0u8; data_size];
+    let data = if oblivion {
+        data[..4].copy_from_slice(&record[20..24]);
+        file.read_exact(&mut data[4..])?;
+        data
+    } else {
+        file.read_exact(&mut data)?;
+        data
+    };
+
+    let mut header = PluginHeader {
+        is_master: flags & MASTER_FLAG != 0,
+        masters: Vec::new(),
+    };
+
+    let mut position = 0;
+    let mut next_size = None;
+    while position + 6 <= data.len() {
+        let kind = &data[position..position + 4];
+        let size = u16::from_le_bytes([data[position + 4], data[position + 5]]) as usize;
+        let size = next_size.take().unwrap_or(size);
+        position += 6;
+
+        let Some(value) = data.get(position..position + size) else {
+            break;
+        };
+        match kind {
+            // Holds the size of the next subrecord when it does not fit in 16 bits
+            b"XXXX" if value.len() == 4 => {
+                next_size = Some(u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as usize);
+            }
+            b"MAST" => {
+                let name = value.split(|b| *b == 0).next().unwrap_or_default();
+                header.masters.push(String::from_utf8_lossy(name).to_string());
+            }
+            _ => {}
+        }
+        position += size;
+    }
+
+    Ok(Some(header))
+}
+
+/// A plugin name in a rule, either plain or as `name: <plugin>`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Reference {
+    Name(String),
+    Detailed { name: String },
+}
+
+impl Reference {
+    fn name(&self) -> &str {
+        match self {
+            Reference::Name(name) => name,
+            Reference::Detailed { name } => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupRule {
+    pub name: String,
+    #[serde(default)]
+    pub after: Vec<Reference>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginRule {
+    pub name: String,
+    #[serde(default)]
+    pub after: Vec<Reference>,
+    #[serde(default)]
+    pub before: Vec<Reference>,
+    pub group: Option<String>,
+}
+
+/// Sorting rules in the shape of a LOOT masterlist. Fields this sorter does
+/// not use, like messages and tags, are ignored.
+#[derive(Debug, Default, Deserialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub groups: Vec<GroupRule>,
+    #[serde(default)]
+    pub plugins: Vec<PluginRule>,
+}
+
+impl Rules {
+    pub fn from_file(path: &Path) -> Result<Self, crate::validate::LoadError> {
+        crate::validate::load_yaml(path)
+    }
+
+    /// Adds the rules of a later file. Rules for the same group or plugin are
+    /// combined, and a group set by `other` wins.
+    pub fn extend(&mut self, other: Rules) {
+        for group in other.groups {
+            match self.groups.iter_mut().find(|g| g.name == group.name) {
+                Some(existing) => existing.after.extend(group.after),
+                None => self.groups.push(group),
+            }
+        }
+
+        for plugin in other.plugins {
+            match self.plugins.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&plugin.name)) {
+                Some(existing) => {
+                    existing.after.extend(plugin.after);
+                    existing.before.extend(plugin.before);
+                    if plugin.group.is_some() {
+                        existing.group = plugin.group;
+                    }
+                }
+                None => self.plugins.push(plugin),
+            }
+        }
+    }
+}
+
+/// Why one plugin or group has to load before another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Master,
+    After,
+    Before,
+    Group,
+}
+
+/// `from` has to load before `to`.
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            Reason::Master => write!(f, "{} has {} as a master", self.to, self.from),
+            Reason::After => write!(f, "a rule loads {} after {}", self.to, self.from),
+            Reason::Before => write!(f, "a rule loads {} before {}", self.from, self.to),
+            Reason::Group => write!(f, "group {} loads after group {}", self.to, self.from),
+        }
+    }
+}
+
+/// A plugin to sort.
+pub struct SortInput {
+    pub name: String,
+    pub header: PluginHeader,
+}
+
+#[derive(Debug, Default)]
+pub struct SortReport {
+    /// The sorted load order. Empty if a cycle was found.
+    pub order: Vec<String>,
+    /// Edges that form a cycle, each requiring the next to load later.
+    pub cycle: Option<Vec<Edge>>,
+}
+
+/// Sorts plugins so that masters and rules are honoured. Masters come before
+/// other plugins and groups keep their order wherever hard constraints allow;
+/// otherwise the input order is kept.
+pub fn sort(plugins: &[SortInput], rules: &Rules) -> SortReport {
+    let index: HashMap<String, usize> = plugins
+        .iter()
+        .enumerate()
+        .map(|(i, plugin)| (plugin.name.to_lowercase(), i))
+        .collect();
+    let lookup = |name: &str| index.get(&name.to_lowercase()).copied();
+
+    let group_ranks = match group_ranks(rules) {
+        Ok(ranks) => ranks,
+        Err(cycle) => {
+            return SortReport { order: Vec::new(), cycle: Some(cycle) };
+        }
+    };
+
+    // Edges between plugin indices, `from` loads before `to`
+    let mut edges: Vec<Vec<(usize, Reason)>> = vec![Vec::new(); plugins.len()];
+    for (to, plugin) in plugins.iter().enumerate() {
+        for master in &plugin.header.masters {
+            if let Some(from) = lookup(master) {
+                edges[from].push((to, Reason::Master));
+            }
+        }
+    }
+
+    let default_rank = group_ranks.get("default").copied().unwrap_or(0);
+    let mut groups = vec![default_rank; plugins.len()];
+    for rule in &rules.plugins {
+        let Some(plugin) = lookup(&rule.name) else {
+            continue;
+        };
+        for after in &rule.after {
+            if let Some(from) = lookup(after.name()) {
+                edges[from].push((plugin, Reason::After));
+            }
+        }
+        for before in &rule.before {
+            if let Some(to) = lookup(before.name()) {
+                edges[plugin].push((to, Reason::Before));
+            }
+        }
+        if let Some(group) = &rule.group {
+            groups[plugin] = group_ranks.get(group.as_str()).copied().unwrap_or(default_rank);
+        }
+    }
+
+    let mut incoming = vec![0usize; plugins.len()];
+    for targets in &edges {
+        for (to, _) in targets {
+            incoming[*to] += 1;
+        }
+    }
+
+    let priority = |i: usize| (!plugins[i].header.is_master, groups[i], i);
+    let mut ready: BinaryHeap<Reverse<(bool, usize, usize)>> = (0..plugins.len())
+        .filter(|i| incoming[*i] == 0)
+        .map(|i| Reverse(priority(i)))
+        .collect();
+
+    let mut order = Vec::with_capacity(plugins.len());
+    while let Some(Reverse((_, _, i))) = ready.pop() {
+        order.push(plugins[i].name.clone());
+        for (to, _) in &edges[i] {
+            incoming[*to] -= 1;
+            if incoming[*to] == 0 {
+                ready.push(Reverse(priority(*to)));
+            }
+        }
+    }
+
+    if order.len() < plugins.len() {
+        let remaining: Vec<bool> = incoming.iter().map(|count| *count > 0).collect();
+        let cycle = find_cycle(&edges, &remaining)
+            .into_iter()
+            .map(|(from, to, reason)| Edge {
+                from: plugins[from].name.clone(),
+                to: plugins[to].name.clone(),
+                reason,
+            })
+            .collect();
+        return SortReport { order: Vec::new(), cycle: Some(cycle) };
+    }
+
+    SortReport { order, cycle: None }
+}
+
+/// The position of every group, counted as the longest chain of groups it
+/// loads after. Plugins without a group are in `default`.
+fn group_ranks(rules: &Rules) -> Result<HashMap<&str, usize>, Vec<Edge>> {
+    let mut ranks: HashMap<&str, usize> = HashMap::new();
+
+    fn visit<'a>(
+        name: &'a str,
+        rules: &'a Rules,
+        ranks: &mut HashMap<&'a str, usize>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<usize, Vec<Edge>> {
+        if let Some(rank) = ranks.get(name) {
+            return Ok(*rank);
+        }
+        if let Some(start) = path.iter().position(|g| *g == name) {
+            let mut cycle: Vec<&str> = path[start..].to_vec();
+            cycle.push(name);
+            // The path runs from later groups to the ones they load after
+            return Err(cycle
+                .windows(2)
+                .map(|pair| Edge {
+                    from: pair[1].to_string(),
+                    to: pair[0].to_string(),
+                    reason: Reason::Group,
+                })
+                .collect());
+        }
+
+        path.push(name);
+        let mut rank = 0;
+        if let Some(group) = rules.groups.iter().find(|g| g.name == name) {
+            for after in &group.after {
+                rank = rank.max(visit(after.name(), rules, ranks, path)? + 1);
+            }
+        }
+        path.pop();
+
+        ranks.insert(name, rank);
+        Ok(rank)
+    }
+
+    for group in &rules.groups {
+        visit(&group.name, rules, &mut ranks, &mut Vec::new())?;
+    }
+    Ok(ranks)
+}
+
+/// Follows edges between the plugins that could not be sorted until one
+/// comes around again. Every such plugin lies on or behind a cycle.
+fn find_cycle(edges: &[Vec<(usize, Reason)>], remaining: &[bool]) -> Vec<(usize, usize, Reason)> {
+    let Some(start) = remaining.iter().position(|r| *r) else {
+        return Vec::new();
+    };
+
+    // Walk backwards, as every remaining plugin has a remaining predecessor
+    let mut predecessor: Vec<Option<(usize, Reason)>> = vec![None; edges.len()];
+    for (from, targets) in edges.iter().enumerate() {
+        if !remaining[from] {
+            continue;
+        }
+        for (to, reason) in targets {
+            if remaining[*to] && predecessor[*to].is_none() {
+                predecessor[*to] = Some((from, *reason));
+            }
+        }
+    }
+
+    let mut seen = vec![false; edges.len()];
+    let mut current = start;
+    while !seen[current] {
+        seen[current] = true;
+        let Some((from, _)) = predecessor[current] else {
+            return Vec::new();
+        };
+        current = from;
+    }
+
+    // `current` is on the cycle now, collect it
+    let mut cycle = Vec::new();
+    let first = current;
+    loop {
+        let (from, reason) = predecessor[current].expect("cycle members have a predecessor");
+        cycle.push((from, current, reason));
+        current = from;
+        if current == first {
+            break;
+        }
+    }
+    cycle.reverse();
+    cycle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Writes a plugin whose `TES4` record claims `data_size` bytes of data,
+    /// followed by `data`.
+    fn plugin(name: &str, data_size: u32, header_end: &[u8; 4], data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("agm-sorting-{}-{}.esp", name, std::process::id()));
+        let mut bytes = b"TES4".to_vec();
+        bytes.extend(data_size.to_le_bytes());
+        bytes.extend([0u8; 12]);
+        bytes.extend(header_end);
+        bytes.extend(data);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_masters() {
+        let mut data = b"MAST".to_vec();
+        data.extend(12u16.to_le_bytes());
+        data.extend(b"Skyrim.esm\0\0");
+        let path = plugin("masters", data.len() as u32, &[0; 4], &data);
+
+        let header = read_header(&path).unwrap().unwrap();
+        assert_eq!(header.masters, vec!["Skyrim.esm".to_string()]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let path = plugin("truncated", 100, &[0; 4], b"MAST");
+        assert!(read_header(&path).is_err());
+        fs::remove_file(path).unwrap();
+
+        let path = plugin("short-oblivion", 2, b"HEDR", &[]);
+        assert_eq!(read_header(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_oversized_header() {
+        let path = plugin("oversized", u32::MAX, &[0; 4], &[]);
+        assert_eq!(read_header(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A value in Valve's KeyValues text format, used by `.vdf` and `.acf` files.
+#[derive(Debug)]
+pub enum Vdf {
+    Str(String),
+    Map(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    pub fn get(&self, key: &str) -> Option<&Vdf> {
+        match self {
+            Vdf::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            Vdf::Str(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::Str(s) => Some(s),
+            Vdf::Map(_) => None,
+        }
+    }
+
+    pub fn entries(&self) -> &[(String, Vdf)] {
+        match self {
+            Vdf::Map(entries) => entries,
+            Vdf::Str(_) => &[],
+        }
+    }
+}
+
+enum Token {
+    Str(String),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            'n' => s.push('\n'),
+                            't' => s.push('\t'),
+                            other => s.push(other),
+                        },
+                        other => s.push(other),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            // Unquoted tokens and conditionals like [$WIN32] are not used by
+            // the files we read
+            _ => {
+                let mut s = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '{' || next == '}' || next == '"' {
+                        break;
+                    }
+                    s.push(next);
+                    chars.next();
+                }
+                if !s.starts_with('[') {
+                    tokens.push(Token::Str(s));
+                }
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// Parses a KeyValues document into a map of its top-level keys.
+pub fn parse_vdf(text: &str) -> Option<Vdf> {
+    let tokens = tokenize(text)?;
+    let mut position = 0;
+    let root = parse_map(&tokens, &mut position)?;
+    Some(root)
+}
+
+fn parse_map(tokens: &[Token], position: &mut usize) -> Option<Vdf> {
+    let mut entries = Vec::new();
+
+    while *position < tokens.len() {
+        let key = match &tokens[*position] {
+            Token::Str(key) => key.clone(),
+            Token::Close => {
+                *position += 1;
+                return Some(Vdf::Map(entries));
+            }
+            Token::Open => return None,
+        };
+        *position += 1;
+
+        let value = match tokens.get(*position)? {
+            Token::Str(value) => {
+                *position += 1;
+                Vdf::Str(value.clone())
+            }
+            Token::Open => {
+                *position += 1;
+                parse_map(tokens, position)?
+            }
+            Token::Close => return None,
+        };
+        entries.push((key, value));
+    }
+    Some(Vdf::Map(entries))
+}
+
+/// A game installed through Steam.
+#[derive(Debug, Clone)]
+pub struct SteamGame {
+    pub appid: u32,
+    pub name: String,
+    pub path: PathBuf,
+    pub library: PathBuf,
+    /// The Proton prefix, if the game has been run through Proton.
+    pub proton_prefix: Option<PathBuf>,
+}
+
+/// The places Steam is usually installed on Linux, natively and as a Flatpak.
+pub fn default_steam_roots() -> Vec<PathBuf> {
+    let Some(home) = dirs_next::home_dir() else {
+        return Vec::new();
+    };
+
+    [
+        ".steam/steam",
+        ".steam/root",
+        ".local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/data/Steam",
+    ]
+    .iter()
+    .map(|dir| home.join(dir))
+    .filter(|dir| dir.join("steamapps").is_dir())
+    .collect()
+}
+
+/// Reads the library folders registered in a Steam install. The install
+/// itself is always a library.
+pub fn library_folders(steam_root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut libraries = vec![steam_root.to_path_buf()];
+
+    let vdf_path = steam_root.join("steamapps").join("libraryfolders.vdf");
+    if !vdf_path.exists() {
+        return Ok(libraries);
+    }
+
+    let Some(vdf) = parse_vdf(&fs::read_to_string(vdf_path)?) else {
+        return Ok(libraries);
+    };
+
+    let folders = vdf.get("libraryfolders").map(Vdf::entries).unwrap_or_default();
+    for (key, value) in folders {
+        // Newer files nest the path, older ones map the index straight to it
+        let path = match value {
+            Vdf::Map(_) => value.get("path").and_then(Vdf::as_str),
+            Vdf::Str(path) if key.chars().all(|c| c.is_ascii_digit()) => Some(path.as_str()),
+            Vdf::Str(_) => None,
+        };
+        if let Some(path) = path {
+            libraries.push(PathBuf::from(path));
+        }
+    }
+    Ok(libraries)
+}
+
+/// Lists the games installed in one library from its `appmanifest_*.acf` files.
+pub fn scan_library(library: &Path) -> io::Result<Vec<SteamGame>> {
+    let steamapps = library.join("steamapps");
+    let mut games = Vec::new();
+
+    if !steamapps.is_dir() {
+        return Ok(games);
+    }
+
+    for entry in fs::read_dir(&steamapps)? {
+        let path = entry?.path();
+        let is_manifest = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|name| name.starts_with("appmanifest_") && name.ends_with(".acf"));
+        if !is_manifest {
+            continue;
+        }
+
+        let Some(manifest) = parse_vdf(&fs::read_to_string(&path)?) else {
+            continue;
+        };
+        let Some(app_state) = manifest.get("AppState") else {
+            continue;
+        };
+
+        let appid = app_state.get("appid").and_then(Vdf::as_str).and_then(|s| s.parse::<u32>().ok());
+        let name = app_state.get("name").and_then(Vdf::as_str);
+        let install_dir = app_state.get("installdir").and_then(Vdf::as_str);
+        let (Some(appid), Some(name), Some(install_dir)) = (appid, name, install_dir) else {
+            continue;
+        };
+
+        let proton_prefix = steamapps
+            .join("compatdata")
+            .join(appid.to_string())
+            .join("pfx");
+
+        games.push(SteamGame {
+            appid,
+            name: name.to_string(),
+            path: steamapps.join("common").join(install_dir),
+            library: library.to_path_buf(),
+            proton_prefix: proton_prefix.is_dir().then_some(proton_prefix),
+        });
+    }
+
+    games.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(games)
+}
+
+/// Finds every game installed in any library of the given Steam installs.
+pub fn detect_games_in(steam_roots: &[PathBuf]) -> io::Result<Vec<SteamGame>> {
+    let mut seen_libraries = HashSet::new();
+    let mut games = Vec::new();
+
+    for steam_root in steam_roots {
+        for library in library_folders(steam_root)? {
+            // ~/.steam/steam is usually a link to one of the other roots
+            let canonical = fs::canonicalize(&library).unwrap_or_else(|_| library.clone());
+            if !seen_libraries.insert(canonical) {
+                continue;
+            }
+            games.extend(scan_library(&library)?);
+        }
+    }
+    Ok(games)
+}
+
+pub fn detect_games() -> io::Result<Vec<SteamGame>> {
+    detect_games_in(&default_steam_roots())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory to build a Steam tree in.
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("agm-steam-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn parses_nested_blocks() {
+        let vdf = parse_vdf(
+            r#"
+            "libraryfolders"
+            {
+                // the Steam install
+                "0"
+                {
+                    "path"  "/home/user/.local/share/Steam"
+                    "apps"
+                    {
+                        "489830"  "12345"
+                    }
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let folder = vdf.get("libraryfolders").and_then(|f| f.get("0")).unwrap();
+        assert_eq!(folder.get("path").and_then(Vdf::as_str), Some("/home/user/.local/share/Steam"));
+        assert_eq!(folder.get("apps").and_then(|a| a.get("489830")).and_then(Vdf::as_str), Some("12345"));
+    }
+
+    #[test]
+    fn parses_escaped_quotes() {
+        let vdf = parse_vdf(r#""AppState" { "name" "The \"Quoted\" Game\\Edition" }"#).unwrap();
+        let name = vdf.get("appstate").and_then(|s| s.get("name")).and_then(Vdf::as_str);
+        assert_eq!(name, Some(r#"The "Quoted" Game\Edition"#));
+    }
+
+    #[test]
+    fn rejects_unbalanced_input() {
+        assert!(parse_vdf(r#""AppState" { "name" "unterminated }"#).is_none());
+        assert!(parse_vdf(r#""AppState" { "name" }"#).is_none());
+    }
+
+    #[test]
+    fn detects_games_in_flatpak_library() {
+        let home = fixture_dir("flatpak");
+        let steam_root = home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam");
+        let library = home.join("Games/SteamLibrary");
+
+        write(
+            &steam_root.join("steamapps/libraryfolders.vdf"),
+            &format!(
+                "\"libraryfolders\"\n{{\n\t\"0\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n}}\n",
+                steam_root.display(),
+                library.display()
+            ),
+        );
+        write(
+            &library.join("steamapps/appmanifest_489830.acf"),
+            "\"AppState\"\n{\n\t\"appid\"\t\t\"489830\"\n\t\"name\"\t\t\"The Elder Scrolls V: Skyrim Special Edition\"\n\t\"installdir\"\t\t\"Skyrim Special Edition\"\n}\n",
+        );
+        fs::create_dir_all(library.join("steamapps/compatdata/489830/pfx")).unwrap();
+
+        let games = detect_games_in(std::slice::from_ref(&steam_root)).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.appid, 489830);
+        assert_eq!(game.library, library);
+        assert_eq!(game.path, library.join("steamapps/common/Skyrim Special Edition"));
+        assert_eq!(game.proton_prefix, Some(library.join("steamapps/compatdata/489830/pfx")));
+
+        fs::remove_dir_all(home).unwrap();
+    }
+}
@@ -29,3 +29,18 @@ pub fn create_symlink(source: &Path, destination: &Path) -> io::Result<()> {
         "Symlinks are not supported on this platform.",
     ))
 }
+
+#[cfg(windows)]
+pub fn remove_symlink(path: &Path) -> io::Result<()> {
+    // Directory links have to be removed as directories on Windows
+    if fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false) {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(not(windows))]
+pub fn remove_symlink(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+}
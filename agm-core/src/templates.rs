@@ -0,0 +1,87 @@
+use crate::profile::Profile;
+
+/// A profile bundled with AGM for a game whose mod layout is well known.
+pub struct Template {
+    pub id: &'static str,
+    pub description: &'static str,
+    /// The Steam app id, used to find the game and pick the template for it.
+    pub appid: Option<u32>,
+    source: &'static str,
+}
+
+impl Template {
+    /// The template as a profile. Paths still hold variables like
+    /// `${STEAM_LIBRARY}` until the profile is given its game.
+    pub fn profile(&self) -> Result<Profile, serde_yaml::Error> {
+        serde_yaml::from_str(self.source)
+    }
+}
+
+static TEMPLATES: &[Template] = &[
+    Template {
+        id: "skyrimse",
+        description: "The Elder Scrolls V: Skyrim Special Edition",
+        appid: Some(489830),
+        source: include_str!("../templates/skyrimse.yaml"),
+    },
+    Template {
+        id: "fallout4",
+        description: "Fallout 4",
+        appid: Some(377160),
+        source: include_str!("../templates/fallout4.yaml"),
+    },
+    Template {
+        id: "stardewvalley",
+        description: "Stardew Valley with SMAPI",
+        appid: Some(413150),
+        source: include_str!("../templates/stardewvalley.yaml"),
+    },
+    Template {
+        id: "valheim",
+        description: "Valheim with BepInEx",
+        appid: Some(892970),
+        source: include_str!("../templates/valheim.yaml"),
+    },
+    Template {
+        id: "bepinex",
+        description: "Any Unity game with BepInEx",
+        appid: None,
+        source: include_str!("../templates/bepinex.yaml"),
+    },
+    Template {
+        id: "minecraft",
+        description: "Minecraft Java Edition",
+        appid: None,
+        source: include_str!("../templates/minecraft.yaml"),
+    },
+    Template {
+        id: "cyberpunk2077",
+        description: "Cyberpunk 2077",
+        appid: Some(1091500),
+        source: include_str!("../templates/cyberpunk2077.yaml"),
+    },
+    Template {
+        id: "baldursgate3",
+        description: "Baldur's Gate 3",
+        appid: Some(1086940),
+        source: include_str!("../templates/baldursgate3.yaml"),
+    },
+    Template {
+        id: "rimworld",
+        description: "RimWorld",
+        appid: Some(294100),
+        source: include_str!("../templates/rimworld.yaml"),
+    },
+];
+
+pub fn list() -> &'static [Template] {
+    TEMPLATES
+}
+
+pub fn get(id: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.id.eq_ignore_ascii_case(id))
+}
+
+pub fn for_appid(appid: u32) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.appid == Some(appid))
+}
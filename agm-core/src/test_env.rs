@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+static ENV: Mutex<()> = Mutex::new(());
+
+/// Points the config and data directories at a fresh directory until the
+/// guard is dropped. The directories come from the environment of the whole
+/// process, so tests using this run one at a time.
+pub fn isolated(name: &str) -> (MutexGuard<'static, ()>, PathBuf) {
+    let guard = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let root = std::env::temp_dir().join(format!("agm-env-{}-{}", name, std::process::id()));
+    if root.exists() {
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    std::fs::create_dir_all(&root).unwrap();
+
+    // SAFETY: every test that reads these variables holds the guard
+    unsafe {
+        std::env::set_var("XDG_CONFIG_HOME", root.join("config"));
+        std::env::set_var("XDG_DATA_HOME", root.join("data"));
+    }
+    (guard, root)
+}
@@ -0,0 +1,189 @@
+use crate::config::Config;
+use crate::history;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const ENTRY_FILE: &str = "trash.yaml";
+const STORAGE_DIR: &str = "storage";
+
+pub fn get_trash_dir() -> io::Result<PathBuf> {
+    Ok(Config::get_data_dir()?.join("trash"))
+}
+
+/// Storage that was purged, kept in `trash/<id>/` until it expires or the
+/// trash is emptied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    #[serde(skip)]
+    pub id: String,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub game: String,
+    /// The purged mod, or `None` for all the mods of the game.
+    #[serde(rename = "mod", default, skip_serializing_if = "Option::is_none")]
+    pub mod_name: Option<String>,
+    /// A game file that was set aside, held instead of storage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl TrashEntry {
+    /// Where the storage is kept in the trash.
+    pub fn path(&self) -> io::Result<PathBuf> {
+        Ok(get_trash_dir()?.join(&self.id).join(STORAGE_DIR))
+    }
+
+    /// Where the storage goes back to when restored.
+    pub fn origin(&self) -> io::Result<PathBuf> {
+        if let Some(file) = &self.file {
+            return Ok(file.clone());
+        }
+        let game_storage = Config::get_data_dir()?.join("storage").join(&self.game);
+        Ok(match &self.mod_name {
+            Some(mod_name) => game_storage.join(mod_name),
+            None => game_storage,
+        })
+    }
+
+    /// The size of the storage in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        let mut size = 0;
+        for entry in WalkDir::new(self.path()?) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                size += entry.metadata()?.len();
+            }
+        }
+        Ok(size)
+    }
+}
+
+/// Moves a mod's storage, or a whole game's without `mod_name`, into a new
+/// trash entry.
+pub fn move_to_trash(game: &str, mod_name: Option<&str>) -> io::Result<TrashEntry> {
+    put(game, mod_name, None)
+}
+
+/// Moves a file out of a game's directories into a new trash entry.
+pub fn move_file_to_trash(game: &str, file: &Path) -> io::Result<TrashEntry> {
+    put(game, None, Some(file))
+}
+
+fn put(game: &str, mod_name: Option<&str>, file: Option<&Path>) -> io::Result<TrashEntry> {
+    let time = history::now();
+    let trash_dir = get_trash_dir()?;
+    let date = &history::format_time(time)[..10];
+
+    let mut n = 1;
+    let id = loop {
+        let id = format!("{}-{}", date, n);
+        if !trash_dir.join(&id).exists() {
+            break id;
+        }
+        n += 1;
+    };
+
+    let entry = TrashEntry {
+        id,
+        time,
+        game: game.to_string(),
+        mod_name: mod_name.map(str::to_string),
+        file: file.map(Path::to_path_buf),
+    };
+    let entry_dir = trash_dir.join(&entry.id);
+    fs::create_dir_all(&entry_dir)?;
+
+    history::rename(&entry.origin()?, &entry.path()?)?;
+    let yaml_string = serde_yaml::to_string(&entry).map_err(io::Error::other)?;
+    fs::write(entry_dir.join(ENTRY_FILE), yaml_string)?;
+    Ok(entry)
+}
+
+/// Every entry still holding storage, oldest first.
+pub fn list() -> io::Result<Vec<TrashEntry>> {
+    let mut entries = Vec::new();
+    for entry in read_entries()? {
+        if entry.path()?.exists() {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Every entry, including those whose storage was taken back out by a
+/// restore or an undo. They are kept so a redo can put the storage back.
+fn read_entries() -> io::Result<Vec<TrashEntry>> {
+    let trash_dir = get_trash_dir()?;
+    if !trash_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for dir in fs::read_dir(trash_dir)? {
+        let dir = dir?;
+        let Some(id) = dir.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let entry_file = dir.path().join(ENTRY_FILE);
+        if !entry_file.exists() {
+            continue;
+        }
+
+        let mut entry: TrashEntry =
+            serde_yaml::from_str(&fs::read_to_string(entry_file)?).map_err(io::Error::other)?;
+        entry.id = id;
+        entries.push(entry);
+    }
+    entries.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.id.cmp(&b.id)));
+    Ok(entries)
+}
+
+pub fn get(id: &str) -> io::Result<Option<TrashEntry>> {
+    Ok(list()?.into_iter().find(|entry| entry.id == id))
+}
+
+/// Moves an entry's storage back to where it was purged from.
+pub fn restore(entry: &TrashEntry) -> io::Result<()> {
+    let origin = entry.origin()?;
+    if origin.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists, remove it before restoring", origin.display()),
+        ));
+    }
+
+    if let Some(parent) = origin.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    history::rename(&entry.path()?, &origin)
+}
+
+/// Deletes every entry for good and returns them.
+pub fn empty() -> io::Result<Vec<TrashEntry>> {
+    let entries = list()?;
+    let trash_dir = get_trash_dir()?;
+    if trash_dir.exists() {
+        fs::remove_dir_all(trash_dir)?;
+    }
+    Ok(entries)
+}
+
+/// Deletes the entries older than `days` days and returns them.
+pub fn expire(days: u64) -> io::Result<Vec<TrashEntry>> {
+    let cutoff = history::now().saturating_sub(days * 86_400);
+    let mut expired = Vec::new();
+    for entry in read_entries()? {
+        if entry.time >= cutoff {
+            continue;
+        }
+        let had_storage = entry.path()?.exists();
+        fs::remove_dir_all(get_trash_dir()?.join(&entry.id))?;
+        if had_storage {
+            expired.push(entry);
+        }
+    }
+    Ok(expired)
+}
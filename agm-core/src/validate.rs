@@ -0,0 +1,194 @@
+use crate::mod_spec::ModSpec;
+use crate::profile::{Layout, LayoutType, Profile};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Why a profile or preset file could not be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Could not read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Could not parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        /// 1-based position of the error, if the parser knows it.
+        line: Option<usize>,
+        column: Option<usize>,
+        source: serde_yaml::Error,
+    },
+    #[error("{path} extends unknown profile or template '{parent}'")]
+    UnknownParent { path: PathBuf, parent: String },
+    #[error("Profiles extend each other in a cycle: {}", .0.join(" -> "))]
+    ExtendsCycle(Vec<String>),
+    #[error("{path} includes unknown preset '{include}'")]
+    UnknownInclude { path: PathBuf, include: String },
+    #[error("Presets include each other in a cycle: {}", .0.join(" -> "))]
+    IncludeCycle(Vec<String>),
+}
+
+impl LoadError {
+    pub fn parse(path: &Path, source: serde_yaml::Error) -> Self {
+        let location = source.location();
+        LoadError::Parse {
+            path: path.to_path_buf(),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            source,
+        }
+    }
+}
+
+/// Reads and deserializes a YAML file, keeping the position of parse errors.
+pub fn load_yaml<T: DeserializeOwned>(path: &Path) -> Result<T, LoadError> {
+    let raw_file_string = fs::read_to_string(path).map_err(|source| LoadError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    serde_yaml::from_str(&raw_file_string).map_err(|source| LoadError::parse(path, source))
+}
+
+/// Whether a name given by the user or read from a file is a single plain
+/// path component, so joining it to a directory stays inside that directory.
+pub fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a profile. Errors make the profile unusable, warnings
+/// are likely mistakes.
+#[derive(Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(message: String) -> Self {
+        Self { severity: Severity::Error, message }
+    }
+
+    fn warning(message: String) -> Self {
+        Self { severity: Severity::Warning, message }
+    }
+}
+
+impl From<LoadError> for Issue {
+    fn from(error: LoadError) -> Self {
+        Issue::error(error.to_string())
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+pub fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+/// Checks a profile for mistakes the parser cannot catch. `specs` are the
+/// installed mods of the game, whose placements must name moddirs that exist.
+pub fn validate_profile(profile: &Profile, specs: &[ModSpec]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let game_path = profile.game_path();
+    if profile.game.path.is_empty() {
+        issues.push(Issue::error("The game path is empty".to_string()));
+    } else if profile.expand(&profile.game.path).is_none() {
+        issues.push(Issue::error(format!(
+            "The game path '{}' uses an unknown variable",
+            profile.game.path
+        )));
+    } else if !game_path.is_dir() {
+        issues.push(Issue::error(format!(
+            "The game path '{}' does not exist",
+            game_path.display()
+        )));
+    }
+
+    for (name, root) in &profile.roots {
+        if profile.expand(root).is_none() {
+            issues.push(Issue::error(format!(
+                "Root '{}' uses an unknown variable or refers to itself",
+                name
+            )));
+        }
+    }
+
+    let mut moddirs: HashMap<&str, usize> = HashMap::new();
+    check_layout(profile, &profile.layout, &mut moddirs, &mut issues);
+
+    let mut duplicates: Vec<_> = moddirs.iter().filter(|(_, count)| **count > 1).collect();
+    duplicates.sort();
+    for (name, count) in duplicates {
+        issues.push(Issue::error(format!(
+            "Moddir '{}' is defined {} times, '@{}' only resolves to the first",
+            name, count, name
+        )));
+    }
+
+    for spec in specs {
+        for file_entry in &spec.files {
+            let Some(moddir) = file_entry.point.strip_prefix('@') else {
+                continue;
+            };
+            if !moddirs.contains_key(moddir) {
+                issues.push(Issue::error(format!(
+                    "Mod '{}' places '{}' in unknown moddir '@{}'",
+                    spec.name, file_entry.target, moddir
+                )));
+            }
+        }
+    }
+
+    issues
+}
+
+fn check_layout<'a>(
+    profile: &Profile,
+    layouts: &'a [Layout],
+    moddirs: &mut HashMap<&'a str, usize>,
+    issues: &mut Vec<Issue>,
+) {
+    for layout in layouts {
+        if let Some(root) = &layout.root
+            && profile.root_path(root).is_none()
+        {
+            issues.push(Issue::error(format!(
+                "Layout node '{}' uses unknown root '{}'",
+                layout.name, root
+            )));
+        }
+
+        if let LayoutType::Moddir = layout.node_type {
+            *moddirs.entry(&layout.name).or_insert(0) += 1;
+
+            if layout.mime.as_ref().is_none_or(|mimes| mimes.is_empty()) {
+                issues.push(Issue::warning(format!(
+                    "Moddir '{}' has no mime rules, files are only placed there by hand",
+                    layout.name
+                )));
+            }
+        }
+
+        if let Some(sub_layouts) = &layout.sub {
+            check_layout(profile, sub_layouts, moddirs, issues);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use walkdir::WalkDir;
+
+/// Differences between the links the active preset expects and what is
+/// actually on disk in the game directory.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Expected links that do not exist at all.
+    pub missing: Vec<(PathBuf, PathBuf)>,
+    /// AGM links whose target is gone or is not the expected storage file.
+    pub dangling: Vec<(PathBuf, PathBuf)>,
+    /// Expected links that were replaced by a real file or a non-AGM link.
+    pub foreign: Vec<(PathBuf, PathBuf)>,
+    /// Links into AGM storage that the active preset does not expect.
+    pub orphaned: Vec<PathBuf>,
+    /// Foreign files a repair left in place because an earlier backup of
+    /// the same path exists.
+    pub kept: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty()
+            && self.dangling.is_empty()
+            && self.foreign.is_empty()
+            && self.orphaned.is_empty()
+    }
+}
+
+/// Verification running on a worker thread, started by a preset switch.
+pub struct BackgroundVerify {
+    handle: JoinHandle<io::Result<VerifyReport>>,
+}
+
+impl BackgroundVerify {
+    pub fn spawn(expected: Vec<(PathBuf, PathBuf)>, roots: Vec<(String, PathBuf)>, storage_root: PathBuf) -> Self {
+        let handle = thread::spawn(move || verify(&expected, &roots, &storage_root));
+        Self { handle }
+    }
+
+    pub fn wait(self) -> io::Result<VerifyReport> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("verification thread panicked")))
+    }
+}
+
+pub fn verify(
+    expected: &[(PathBuf, PathBuf)],
+    roots: &[(String, PathBuf)],
+    storage_root: &Path,
+) -> io::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+
+    for (source, dest) in expected {
+        let Ok(metadata) = dest.symlink_metadata() else {
+            report.missing.push((source.clone(), dest.clone()));
+            continue;
+        };
+
+        if !metadata.file_type().is_symlink() {
+            report.foreign.push((source.clone(), dest.clone()));
+            continue;
+        }
+
+        let target = fs::read_link(dest)?;
+        if !target.starts_with(storage_root) {
+            report.foreign.push((source.clone(), dest.clone()));
+        } else if &target != source || !source.exists() {
+            report.dangling.push((source.clone(), dest.clone()));
+        }
+    }
+
+    report.orphaned = find_orphans(expected, roots, storage_root)?;
+    Ok(report)
+}
+
+/// Walks the deployment roots for links into AGM storage that are not expected.
+fn find_orphans(
+    expected: &[(PathBuf, PathBuf)],
+    roots: &[(String, PathBuf)],
+    storage_root: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    let expected_dests: HashSet<&Path> = expected.iter().map(|(_, dest)| dest.as_path()).collect();
+    let mut seen = HashSet::new();
+    let mut orphans = Vec::new();
+
+    for (_, root) in roots {
+        for dest in find_agm_links(root, storage_root)? {
+            // Roots may be nested inside each other
+            if !expected_dests.contains(dest.as_path()) && seen.insert(dest.clone()) {
+                orphans.push(dest);
+            }
+        }
+    }
+    Ok(orphans)
+}
+
+/// Finds every link under `root` that points into `storage_root`, including
+/// links whose target no longer exists.
+pub fn find_agm_links(root: &Path, storage_root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut links = Vec::new();
+
+    if !root.exists() {
+        return Ok(links);
+    }
+
+    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.path_is_symlink() {
+            continue;
+        }
+
+        let target = fs::read_link(entry.path())?;
+        if target.starts_with(storage_root) {
+            links.push(entry.path().to_path_buf());
+        }
+    }
+    Ok(links)
+}
+
+/// Moves every backed up file back into its deployment root. Backups whose
+/// original location is occupied again are left in place.
+pub fn restore_backups(roots: &[(String, PathBuf)], backup_root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut restored = Vec::new();
+
+    for (name, root) in roots {
+        restored.extend(restore_root_backups(root, &backup_root.join(name))?);
+    }
+    Ok(restored)
+}
+
+fn restore_root_backups(root: &Path, backup_root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut restored = Vec::new();
+
+    if !backup_root.exists() {
+        return Ok(restored);
+    }
+
+    for entry in WalkDir::new(backup_root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(backup_root).unwrap_or(entry.path());
+        let dest = root.join(relative);
+        if dest.exists() || dest.is_symlink() {
+            continue;
+        }
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::rename(entry.path(), &dest).is_err() {
+            fs::copy(entry.path(), &dest)?;
+            fs::remove_file(entry.path())?;
+        }
+        restored.push(dest);
+    }
+
+    // Drop the directories that are now empty, deepest first
+    for entry in WalkDir::new(backup_root).contents_first(true).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_dir() {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+    Ok(restored)
+}
+
+/// Fixes every issue in `report`. Foreign files are moved into `backup_root`,
+/// keeping their path relative to their deployment root, before being linked
+/// over. A foreign file whose path is already backed up is not touched, and
+/// is returned instead.
+pub fn repair(
+    game: &str,
+    report: &VerifyReport,
+    roots: &[(String, PathBuf)],
+    backup_root: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    for dest in &report.orphaned {
+        crate::symlink::remove_symlink(dest)?;
+    }
+
+    for (_, dest) in &report.dangling {
+        crate::symlink::remove_symlink(dest)?;
+    }
+
+    let mut kept = Vec::new();
+    for (_, dest) in &report.foreign {
+        if !backup_file(dest, roots, backup_root)? {
+            kept.push(dest.clone());
+        }
+    }
+
+    let to_link = report
+        .missing
+        .iter()
+        .chain(&report.dangling)
+        .chain(&report.foreign);
+
+    for (source, dest) in to_link {
+        if !source.exists() || kept.contains(dest) {
+            continue;
+        }
+        crate::deploy::create_link(game, source, dest)?;
+    }
+    Ok(kept)
+}
+
+/// Moves a file into the backup area. An existing backup is never replaced,
+/// it holds the original file, so `false` is returned without moving anything.
+fn backup_file(dest: &Path, roots: &[(String, PathBuf)], backup_root: &Path) -> io::Result<bool> {
+    // The innermost root wins when roots are nested
+    let (name, relative) = roots
+        .iter()
+        .filter_map(|(name, root)| dest.strip_prefix(root).ok().map(|relative| (name, relative)))
+        .min_by_key(|(_, relative)| relative.components().count())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is outside of every deployment root", dest.display()),
+            )
+        })?;
+    let backup_path = backup_root.join(name).join(relative);
+    if backup_path.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = backup_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Storage and game dirs are often on different drives
+    crate::history::rename(dest, &backup_path)?;
+    Ok(true)
+}
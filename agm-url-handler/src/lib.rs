@@ -1,4 +1,3 @@
-use agm_core::ipc::UrlMessage;
 use std::path::PathBuf;
 
 pub fn get_socket_path() -> PathBuf {
@@ -1,4 +1,5 @@
-use agm_url_handler::{get_socket_path, UrlMessage};
+use agm_core::ipc::UrlMessage;
+use agm_url_handler::get_socket_path;
 use clap::Parser;
 use std::process;
 use tokio::io::AsyncWriteExt;
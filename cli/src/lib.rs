@@ -1,9 +1,16 @@
+use agm_core::compare::Side;
+use agm_core::deps::Problem;
+use agm_core::history;
 use agm_core::install::InstallReporter;
-use agm_core::Agm;
+use agm_core::steam::SteamGame;
+use agm_core::validate::{self, Issue};
+use agm_core::verify::{BackgroundVerify, VerifyReport};
+use agm_core::{Agm, Error, RecoveredSwitch, Replayed};
 pub use clap::Parser;
 use clap::{CommandFactory, Subcommand, ValueHint};
+use std::collections::HashSet;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -34,6 +41,29 @@ pub enum Command {
         #[command(subcommand)]
         cmd: CliMod,
     },
+    Verify {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Save and bring back everything AGM controls for a game
+    Snapshot {
+        #[command(subcommand)]
+        cmd: CliSnapshot,
+    },
+    /// Purged mods kept until the trash expires or is emptied
+    Trash {
+        #[command(subcommand)]
+        cmd: CliTrash,
+    },
+    /// Revert the last change AGM made
+    Undo,
+    /// Apply the last undone change again
+    Redo,
+    /// List the changes that can be undone and redone
+    History,
     #[command(hide = true)]
     Completion {
         #[command(subcommand)]
@@ -50,6 +80,45 @@ pub enum Completion {
     Elvish,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum CliSnapshot {
+    Create {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+
+        /// Defaults to the current date and time
+        name: Option<String>,
+    },
+    List {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+    },
+    Restore {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+
+        name: String,
+    },
+    Delete {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliTrash {
+    List,
+    /// Move purged storage back to where it was
+    Restore {
+        /// The id shown by 'agm trash list'
+        id: String,
+    },
+    /// Delete everything in the trash for good
+    Empty,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum CliMod {
     Install(CliInstall),
@@ -66,6 +135,28 @@ pub enum CliMod {
         #[arg(value_hint = ValueHint::Other)]
         game: String,
     },
+    /// Turn a mod back on in a preset
+    Enable {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+
+        name: String,
+
+        /// Defaults to the active preset
+        #[arg(long)]
+        preset: Option<String>,
+    },
+    /// Turn a mod off in a preset without removing it
+    Disable {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+
+        name: String,
+
+        /// Defaults to the active preset
+        #[arg(long)]
+        preset: Option<String>,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -89,6 +180,10 @@ pub struct CliConfig {
     /// Set the editor to use for editing profiles
     #[arg(long)]
     pub editor: Option<String>,
+
+    /// Set how many days purged mods stay in the trash, 0 keeps them
+    #[arg(long)]
+    pub trash_expiry_days: Option<u64>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -100,18 +195,49 @@ pub enum CliProfile {
         game: String,
         name: Option<String>,
         content: Option<String>,
+        /// Start from a bundled profile, see `agm profile templates`
+        #[arg(long, conflicts_with = "content")]
+        template: Option<String>,
     },
 
+    /// List the bundled profile templates
+    Templates,
+
     Edit {
         #[arg(value_hint = ValueHint::Other)]
         game: String,
         content: Option<String>,
     },
 
+    /// Print a profile
+    Show {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        /// Merge in the profiles and templates it extends
+        #[arg(long)]
+        resolved: bool,
+    },
+
+    /// Check a profile for mistakes
+    Check {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+    },
+
     Remove {
         #[arg(value_hint = ValueHint::Other)]
         game: String,
     },
+
+    /// Give a profile another name, or list its aliases without one
+    Alias {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        alias: Option<String>,
+        /// Remove the alias instead
+        #[arg(long, requires = "alias")]
+        remove: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -125,6 +251,9 @@ pub enum CliPreset {
         #[arg(value_hint = ValueHint::Other)]
         game: String,
         preset: String,
+        /// Activate the preset even if some of its mods are not installed
+        #[arg(long)]
+        allow_missing: bool,
     },
 
     List {
@@ -153,6 +282,68 @@ pub enum CliPreset {
         game: String,
         name: String,
     },
+
+    Disable {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+    },
+
+    /// Sort the active preset's plugins by their masters and rules
+    Sort {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+    },
+
+    /// Record the installed version, archive hash and placements of every mod
+    Lock {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        preset: String,
+    },
+
+    /// Compare the installed mods with the preset's lockfile
+    Outdated {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        preset: String,
+    },
+
+    /// Pack a preset into a shareable .agmpack
+    Export {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        preset: String,
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+        /// Pack the mod files instead of only their urls and hashes
+        #[arg(long)]
+        with_files: bool,
+    },
+
+    /// Recreate the presets and mods of an .agmpack
+    Import {
+        #[arg(value_hint = ValueHint::FilePath)]
+        bundle: PathBuf,
+    },
+
+    /// Show what changes from one preset to another
+    Diff {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        first: String,
+        second: String,
+    },
+
+    /// Combine two presets, asking which entry to keep on conflicts
+    Merge {
+        #[arg(value_hint = ValueHint::Other)]
+        game: String,
+        first: String,
+        second: String,
+        /// The preset to write, defaults to the first one
+        #[arg(long)]
+        into: Option<String>,
+    },
 }
 
 struct CliInstallReporter;
@@ -279,6 +470,52 @@ impl InstallReporter for CliInstallReporter {
         Ok(input == "y" || input == "Y")
     }
 
+    fn confirm_fetch(&self, mods: &[(&str, &str)]) -> io::Result<bool> {
+        println!("These mods can be downloaded:");
+        for (name, url) in mods {
+            println!("    {} ({})", name, url);
+        }
+        print!("Download and install them now? (y/N): ");
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+        Ok(input == "y" || input == "Y")
+    }
+
+    fn download_start(&self, mod_name: &str, url: &str) {
+        println!("Downloading '{}' from {}...", mod_name, url);
+    }
+
+    fn choose_merge_side(&self, conflict: &str, first: &str, second: &str) -> io::Result<Side> {
+        println!("\nBoth presets set up {} differently.", conflict);
+        println!("  1) first preset:");
+        for line in first.lines() {
+            println!("       {}", line);
+        }
+        println!("  2) second preset:");
+        for line in second.lines() {
+            println!("       {}", line);
+        }
+
+        loop {
+            print!("Keep which one? (1/2): ");
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            match input.trim() {
+                "1" => return Ok(Side::First),
+                "2" => return Ok(Side::Second),
+                _ => println!("Invalid input. Please enter 1 or 2."),
+            }
+        }
+    }
+
+    fn prompt_for_profile_mapping(&self, game: &str, profiles: &[String]) -> io::Result<String> {
+        println!("The bundle was made for '{}', which has no profile here.", game);
+        self.prompt_for_profile(profiles)
+    }
+
     fn prompt_for_presets(&self, presets: &[String]) -> io::Result<Vec<String>> {
         println!("Please choose presets to add the mod to (e.g., 1 3):");
         for (i, preset) in presets.iter().enumerate() {
@@ -305,7 +542,7 @@ impl InstallReporter for CliInstallReporter {
         io::stdin().read_line(&mut input)?;
         let remove_presets = input.trim().eq_ignore_ascii_case("y");
 
-        print!("Do you want to remove all associated mod files from storage? (y/N): ");
+        print!("Do you want to move all associated mod files to the trash? (y/N): ");
         io::stdout().flush()?;
         input.clear();
         io::stdin().read_line(&mut input)?;
@@ -313,6 +550,278 @@ impl InstallReporter for CliInstallReporter {
 
         Ok((remove_presets, remove_mods))
     }
+
+    fn prompt_for_steam_game(&self, games: &[SteamGame]) -> io::Result<Option<usize>> {
+        println!("Found these games in your Steam libraries:");
+        for (i, game) in games.iter().enumerate() {
+            println!("    {}) {} ({})", i + 1, game.name, game.path.display());
+        }
+        println!("    [c] Custom Path");
+
+        loop {
+            print!("Your choice: ");
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            let input = input.trim();
+            if input == "c" {
+                return Ok(None);
+            } else if let Ok(choice_index) = input.parse::<usize>() {
+                if choice_index > 0 && choice_index <= games.len() {
+                    return Ok(Some(choice_index - 1));
+                } else {
+                    println!("Invalid number. Please try again.");
+                }
+            } else {
+                println!("Invalid input. Please choose a number or 'c'.");
+            }
+        }
+    }
+}
+
+/// Steam games whose name looks like `game`, or all of them if none does.
+fn matching_steam_games(game: &str, games: Vec<SteamGame>) -> Vec<SteamGame> {
+    let normalize = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let wanted = normalize(game);
+
+    let matches: Vec<SteamGame> = games
+        .iter()
+        .filter(|g| {
+            let name = normalize(&g.name);
+            name.contains(&wanted) || wanted.contains(&name)
+        })
+        .cloned()
+        .collect();
+
+    if matches.is_empty() { games } else { matches }
+}
+
+fn print_issues(issues: &[Issue]) {
+    for issue in issues {
+        eprintln!("  {}", issue);
+    }
+}
+
+/// Runs `edit` and then `check`, reopening the editor as long as the file has
+/// errors. Content given on the command line has no editor to reopen, so its
+/// issues are only printed.
+fn edit_until_valid(
+    agm: &mut Agm,
+    interactive: bool,
+    edit: impl Fn(&mut Agm) -> Result<(), agm_core::Error>,
+    check: impl Fn(&Agm) -> Result<Vec<Issue>, agm_core::Error>,
+) -> Result<(), agm_core::Error> {
+    loop {
+        edit(agm)?;
+        let issues = check(agm)?;
+        print_issues(&issues);
+
+        if !interactive || !validate::has_errors(&issues) {
+            return Ok(());
+        }
+
+        print!("Press Enter to fix the errors in the editor, or type 'k' to keep the file as is: ");
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        if input.trim().eq_ignore_ascii_case("k") {
+            return Ok(());
+        }
+    }
+}
+
+fn print_verify_report(game: &str, report: &VerifyReport) {
+    if report.is_clean() {
+        println!("Game '{}' matches its active preset.", game);
+        return;
+    }
+
+    for (_, dest) in &report.missing {
+        println!("  missing:  {}", dest.display());
+    }
+    for (source, dest) in &report.dangling {
+        println!("  dangling: {} -> {}", dest.display(), source.display());
+    }
+    for (_, dest) in &report.foreign {
+        println!("  foreign:  {}", dest.display());
+    }
+    for dest in &report.orphaned {
+        println!("  orphaned: {}", dest.display());
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn print_blocked_links(replayed: &Replayed) {
+    if replayed.blocked.is_empty() {
+        return;
+    }
+
+    println!("These links were not restored because files are in the way:");
+    for dest in &replayed.blocked {
+        println!("  {}", dest.display());
+    }
+    for game in &replayed.operation.games {
+        println!("Run 'agm verify {} --repair' to back the files up and link them.", game);
+    }
+}
+
+fn print_recovered_switch(recovered: &RecoveredSwitch) {
+    if recovered.committed {
+        println!("Finished the interrupted switch of {} to preset '{}'.", recovered.game, recovered.to);
+    } else {
+        println!(
+            "Undid the interrupted switch of {} to preset '{}', {} is back on {}.",
+            recovered.game,
+            recovered.to,
+            recovered.game,
+            recovered.from.as_deref().map(|p| format!("preset '{}'", p)).unwrap_or_else(|| "no preset".to_string())
+        );
+    }
+
+    if !recovered.blocked.is_empty() {
+        println!("These links could not be removed or put back:");
+        for dest in &recovered.blocked {
+            println!("  {}", dest.display());
+        }
+        println!("Run 'agm verify {} --repair' to fix them.", recovered.game);
+    }
+    if let Some(e) = &recovered.load_order_error {
+        eprintln!("Could not rewrite the plugin list of {}: {}", recovered.game, e);
+    }
+}
+
+/// Enables or disables a mod and reports the links that changed.
+fn set_mod_enabled(agm: &mut Agm, game: &str, name: &str, preset: Option<&str>, enabled: bool) {
+    let changes = match agm.set_mod_enabled(game, name, preset, enabled) {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("Error {} mod: {}", if enabled { "enabling" } else { "disabling" }, e);
+            return;
+        }
+    };
+
+    println!("{} mod '{}'.", if enabled { "Enabled" } else { "Disabled" }, name);
+    if !changes.removed.is_empty() || !changes.added.is_empty() {
+        println!(
+            "Redeployed: {} link(s) removed, {} link(s) added.",
+            changes.removed.len(),
+            changes.added.len()
+        );
+    }
+
+    // Turning a mod off may leave others without a dependency
+    let preset = preset
+        .map(str::to_string)
+        .or_else(|| {
+            let game = agm.resolve_game(game);
+            agm.get_presets().iter().find(|g| g.profile == game).and_then(|g| g.active_preset.clone())
+        });
+    if let Some(preset) = preset
+        && let Ok(report) = agm.check_dependencies(game, &preset)
+    {
+        for problem in &report.problems {
+            eprintln!("warning: {}", problem);
+        }
+    }
+}
+
+/// Reports what keeps a preset from being activated and offers to download
+/// the mods that can be. With `allow_missing`, mods that are not installed do
+/// not keep the preset from being activated. Returns whether the switch should
+/// go ahead.
+fn resolve_dependencies(agm: &mut Agm, game: &str, preset: &str, allow_missing: bool) -> bool {
+    let reporter = CliInstallReporter;
+    // Mods are only offered once, so a failing download is not retried
+    let mut attempted = HashSet::new();
+
+    loop {
+        let report = match agm.check_dependencies(game, preset) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Error checking dependencies: {}", e);
+                return false;
+            }
+        };
+        if report.is_ok() {
+            return true;
+        }
+
+        let blocking = report
+            .problems
+            .iter()
+            .any(|p| !(allow_missing && matches!(p, Problem::NotInstalled { .. })));
+        if blocking {
+            eprintln!("Preset '{}' cannot be activated as it is:", preset);
+        } else {
+            eprintln!("Preset '{}' will be activated without these mods:", preset);
+        }
+        for problem in &report.problems {
+            eprintln!("  {}", problem);
+        }
+
+        let mut fetchable: Vec<(&str, &str)> = report.problems.iter().filter_map(|p| p.fetchable()).collect();
+        fetchable.retain(|(name, _)| attempted.insert(name.to_lowercase()));
+        if fetchable.is_empty() || !reporter.confirm_fetch(&fetchable).unwrap_or(false) {
+            return !blocking;
+        }
+
+        let failed = match agm.acquire_mods(game, preset, &fetchable, &reporter) {
+            Ok(failed) => failed,
+            Err(e) => {
+                eprintln!("Error downloading mods: {}", e);
+                return false;
+            }
+        };
+        for (name, e) in &failed {
+            eprintln!("Error fetching '{}': {}", name, e);
+        }
+
+        // Required mods that were not in the preset yet join it
+        let missing: Vec<String> = report
+            .problems
+            .iter()
+            .filter_map(|p| match p {
+                Problem::Missing { dependency, .. } if dependency.url().is_some() => Some(dependency.name().to_string()),
+                _ => None,
+            })
+            .filter(|name| !failed.iter().any(|(failed, _)| failed == name))
+            .collect();
+        if let Err(e) = agm.add_mods_to_preset(game, preset, &missing) {
+            eprintln!("Error adding mods to preset: {}", e);
+            return false;
+        }
+    }
+}
+
+fn wait_for_verify(game: &str, verification: BackgroundVerify) {
+    match verification.wait() {
+        Ok(report) => {
+            if !report.is_clean() {
+                println!("Deployment issues found, run 'agm verify {} --repair' to fix them:", game);
+                print_verify_report(game, &report);
+            }
+        }
+        Err(e) => eprintln!("Error verifying game '{}': {}", game, e),
+    }
 }
 
 pub fn run(args: Args) {
@@ -323,6 +832,9 @@ pub fn run(args: Args) {
             return;
         }
     };
+    for recovered in agm.recovered_switches() {
+        print_recovered_switch(recovered);
+    }
 
     match args.command {
         Some(Command::Completion {
@@ -336,6 +848,9 @@ pub fn run(args: Args) {
                     if arg == "profile" || arg == "game" {
                         for profile in agm.get_profile_names() {
                             println!("{}", profile);
+                            for alias in agm.aliases(&profile) {
+                                println!("{}", alias);
+                            }
                         }
                         return;
                     }
@@ -395,8 +910,63 @@ pub fn run(args: Args) {
                 } else {
                     println!("Profiles:");
                     for profile in profiles {
-                        println!("  - {}", profile);
+                        let aliases = agm.aliases(&profile);
+                        if aliases.is_empty() {
+                            println!("  - {}", profile);
+                        } else {
+                            println!("  - {} (also {})", profile, aliases.join(", "));
+                        }
+                    }
+                }
+            }
+
+            CliProfile::Templates => {
+                println!("Templates:");
+                for template in agm.get_templates() {
+                    println!("  - {:<16} {}", template.id, template.description);
+                }
+            }
+
+            CliProfile::Add {
+                game,
+                name,
+                content: _,
+                template: Some(template),
+            } => {
+                let profile_name = name.clone().unwrap_or_else(|| game.clone());
+                let Some(found) = agm_core::templates::get(&template) else {
+                    eprintln!("Error adding profile: Profile template '{}' not found", template);
+                    return;
+                };
+
+                let steam_game = found.appid.and_then(|appid| {
+                    agm.detect_steam_games()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .find(|g| g.appid == appid)
+                });
+
+                let game_path = match &steam_game {
+                    Some(steam_game) => {
+                        println!("Found {} at {}", steam_game.name, steam_game.path.display());
+                        None
                     }
+                    None => {
+                        println!("Please enter the full path to the game's base dir (leave empty to keep the template's default):");
+                        let mut game_path = String::new();
+                        if io::stdin().read_line(&mut game_path).is_err() {
+                            eprintln!("Error reading game path");
+                            return;
+                        }
+                        let game_path = game_path.trim();
+                        (!game_path.is_empty()).then(|| game_path.to_string())
+                    }
+                };
+
+                if let Err(e) = agm.add_profile_from_template(game, name, &template, steam_game.as_ref(), game_path) {
+                    eprintln!("Error adding profile: {}", e);
+                } else {
+                    println!("Created profile '{}' from template '{}'.", profile_name, template);
                 }
             }
 
@@ -404,7 +974,33 @@ pub fn run(args: Args) {
                 game,
                 name,
                 content,
+                template: None,
             } => {
+                let profile_name = name.clone().unwrap_or_else(|| game.clone());
+
+                if content.is_none() {
+                    let detected = agm.detect_steam_games().unwrap_or_default();
+                    if !detected.is_empty() {
+                        let reporter = CliInstallReporter;
+                        let candidates = matching_steam_games(&game, detected);
+                        match reporter.prompt_for_steam_game(&candidates) {
+                            Ok(Some(index)) => {
+                                if let Err(e) = agm.add_profile_from_steam(game, name, &candidates[index]) {
+                                    eprintln!("Error adding profile: {}", e);
+                                } else {
+                                    println!("Created profile '{}'.", profile_name);
+                                }
+                                return;
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                eprintln!("Error reading choice: {}", e);
+                                return;
+                            }
+                        }
+                    }
+                }
+
                 let game_path = if content.is_none() {
                     println!("Please enter the full path to the game's base dir:");
                     let mut game_path = String::new();
@@ -417,7 +1013,6 @@ pub fn run(args: Args) {
                     None
                 };
 
-                let profile_name = name.clone().unwrap_or_else(|| game.clone());
                 if let Err(e) = agm.add_profile(game, name, content, game_path) {
                     eprintln!("Error adding profile: {}", e);
                 } else {
@@ -426,11 +1021,32 @@ pub fn run(args: Args) {
             }
 
             CliProfile::Edit { game, content } => {
-                if let Err(e) = agm.edit_profile(&game, content) {
+                let interactive = content.is_none();
+                let result = edit_until_valid(
+                    &mut agm,
+                    interactive,
+                    |agm| agm.edit_profile(&game, content.clone()),
+                    |agm| agm.check_profile(&game),
+                );
+                if let Err(e) = result {
                     eprintln!("Error editing profile: {}", e);
                 }
             }
 
+            CliProfile::Show { game, resolved } => match agm.show_profile(&game, resolved) {
+                Ok(yaml) => print!("{}", yaml),
+                Err(e) => eprintln!("Error showing profile: {}", e),
+            },
+
+            CliProfile::Check { game } => match agm.check_profile(&game) {
+                Ok(issues) if issues.is_empty() => println!("Profile '{}' is valid.", game),
+                Ok(issues) => {
+                    println!("Profile '{}' has {} issue(s):", game, issues.len());
+                    print_issues(&issues);
+                }
+                Err(e) => eprintln!("Error checking profile: {}", e),
+            },
+
             CliProfile::Remove { game } => {
                 let reporter = CliInstallReporter;
                 if let Ok((remove_presets, remove_mods)) = reporter.confirm_profile_parts_removal()
@@ -442,6 +1058,27 @@ pub fn run(args: Args) {
                     }
                 }
             }
+
+            CliProfile::Alias { game, alias: None, .. } => {
+                let aliases = agm.aliases(&game);
+                if aliases.is_empty() {
+                    println!("Profile '{}' has no aliases.", agm.resolve_game(&game));
+                } else {
+                    println!("Aliases of {}:", agm.resolve_game(&game));
+                    for alias in aliases {
+                        println!("  - {}", alias);
+                    }
+                }
+            }
+
+            CliProfile::Alias { game, alias: Some(alias), remove } => {
+                let result = if remove { agm.remove_alias(&game, &alias) } else { agm.add_alias(&game, &alias) };
+                match result {
+                    Ok(()) if remove => println!("Removed alias '{}'.", alias),
+                    Ok(()) => println!("'{}' now refers to profile '{}'.", alias, agm.resolve_game(&game)),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            }
         },
 
         Some(Command::Preset { cmd }) => match cmd {
@@ -459,27 +1096,47 @@ pub fn run(args: Args) {
                 }
 
                 if active == "" {
-                    if let Err(e) = agm.switch_preset(&game, &active) {
-                        eprintln!("error reloding preset: {}", e);
-                        return;
-                    }
+                    let verification = match agm.switch_preset(&game, &active, true) {
+                        Ok(verification) => verification,
+                        Err(e) => {
+                            eprintln!("error reloding preset: {}", e);
+                            return;
+                        }
+                    };
 
                     println!("Reloded");
+                    wait_for_verify(&game, verification);
                 }
             }
 
-            CliPreset::Switch { game, preset } => {
-                if let Err(e) = agm.switch_preset(&game, &preset) {
-                    eprintln!("error switching preset: {}", e);
+            CliPreset::Switch { game, preset, allow_missing } => {
+                if !resolve_dependencies(&mut agm, &game, &preset, allow_missing) {
                     return;
                 }
 
+                let verification = match agm.switch_preset(&game, &preset, allow_missing) {
+                    Ok(verification) => verification,
+                    Err(Error::LockMismatch(mismatches)) => {
+                        eprintln!("Preset '{}' does not match its lockfile:", preset);
+                        for mismatch in &mismatches {
+                            eprintln!("  {}", mismatch);
+                        }
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("error switching preset: {}", e);
+                        return;
+                    }
+                };
+
                 println!("Switched to preset '{}' for game '{}'.", preset, game);
+                wait_for_verify(&game, verification);
             }
 
             CliPreset::List { profile } => {
                 let presets = agm.get_presets();
                 if let Some(game_name) = profile {
+                    let game_name = agm.resolve_game(&game_name);
                     if let Some(preset_config) = presets.iter().find(|p| p.profile == game_name) {
                         println!("Presets for {}:", game_name);
                         for preset in &preset_config.presets {
@@ -566,7 +1223,14 @@ pub fn run(args: Args) {
                 name,
                 content,
             } => {
-                if let Err(e) = agm.edit_preset(&game, &name, content) {
+                let interactive = content.is_none();
+                let result = edit_until_valid(
+                    &mut agm,
+                    interactive,
+                    |agm| agm.edit_preset(&game, &name, content.clone()),
+                    |agm| agm.check_preset(&game, &name),
+                );
+                if let Err(e) = result {
                     eprintln!("Error editing preset: {}", e);
                 }
             }
@@ -579,6 +1243,117 @@ pub fn run(args: Args) {
 
                 println!("Removed preset '{}' for game '{}'.", name, game);
             }
+
+            CliPreset::Disable { game } => match agm.purge_game(&game) {
+                Ok((removed, restored)) => {
+                    println!(
+                        "Disabled mods for game '{}': removed {} links, restored {} files.",
+                        game,
+                        removed.len(),
+                        restored.len()
+                    );
+                }
+                Err(e) => eprintln!("Error disabling mods: {}", e),
+            },
+
+            CliPreset::Sort { game } => match agm.sort_load_order(&game) {
+                Ok((report, missing_masters)) => {
+                    for (plugin, master) in &missing_masters {
+                        eprintln!("warning: {} needs {}, which is not installed", plugin, master);
+                    }
+
+                    match &report.cycle {
+                        Some(cycle) => {
+                            eprintln!("Could not sort the plugins of '{}', the rules form a cycle:", game);
+                            for edge in cycle {
+                                eprintln!("  {}", edge);
+                            }
+                        }
+                        None => {
+                            println!("Load order for '{}':", game);
+                            for (i, plugin) in report.order.iter().enumerate() {
+                                println!("  {:>3} {}", i + 1, plugin);
+                            }
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Error sorting plugins: {}", e),
+            },
+
+            CliPreset::Lock { game, preset } => match agm.lock_preset(&game, &preset) {
+                Ok(lockfile) => {
+                    println!("Locked {} mod(s) of preset '{}'.", lockfile.mods.len(), preset);
+                    for locked in lockfile.mods.iter().filter(|m| m.sha256.is_none()) {
+                        eprintln!(
+                            "warning: {} was installed without a recorded archive hash, reinstall it to lock its hash",
+                            locked.name
+                        );
+                    }
+                }
+                Err(Error::Dependencies(report)) => {
+                    eprintln!("Preset '{}' cannot be locked as it is:", preset);
+                    for problem in &report.problems {
+                        eprintln!("  {}", problem);
+                    }
+                }
+                Err(e) => eprintln!("Error locking preset: {}", e),
+            },
+
+            CliPreset::Export { game, preset, output, with_files } => {
+                match agm.export_preset(&game, &preset, &output, with_files) {
+                    Ok(()) => println!("Exported preset '{}' to {}.", preset, output.display()),
+                    Err(e) => eprintln!("Error exporting preset: {}", e),
+                }
+            }
+
+            CliPreset::Import { bundle } => match agm.import_bundle(&bundle, &CliInstallReporter) {
+                Ok((game, preset)) => {
+                    println!("Imported preset '{}' for game '{}'.", preset, game);
+                    println!("Run 'agm preset switch {} {}' to download missing mods and activate it.", game, preset);
+                }
+                Err(e) => eprintln!("Error importing bundle: {}", e),
+            },
+
+            CliPreset::Diff { game, first, second } => match agm.diff_presets(&game, &first, &second) {
+                Ok(diff) if diff.is_empty() => println!("Presets '{}' and '{}' are the same.", first, second),
+                Ok(diff) => {
+                    println!("Changes from '{}' to '{}':", first, second);
+                    for name in &diff.added {
+                        println!("  + {}", name);
+                    }
+                    for name in &diff.removed {
+                        println!("  - {}", name);
+                    }
+                    for moved in &diff.moved {
+                        println!("  ~ {}: moved from {} to {}", moved.name, moved.from, moved.to);
+                    }
+                    for change in &diff.changed {
+                        println!("  ~ {}", change);
+                    }
+                }
+                Err(e) => eprintln!("Error comparing presets: {}", e),
+            },
+
+            CliPreset::Merge { game, first, second, into } => {
+                let into = into.unwrap_or_else(|| first.clone());
+                match agm.merge_presets(&game, &first, &second, &into, &CliInstallReporter) {
+                    Ok(()) => println!("Merged '{}' and '{}' into '{}'.", first, second, into),
+                    Err(e) => eprintln!("Error merging presets: {}", e),
+                }
+            }
+
+            CliPreset::Outdated { game, preset } => match agm.outdated(&game, &preset) {
+                Ok(differences) if differences.is_empty() => {
+                    println!("Preset '{}' matches its lockfile.", preset);
+                }
+                Ok(differences) => {
+                    println!("Preset '{}' differs from its lockfile:", preset);
+                    for difference in &differences {
+                        println!("  {}", difference);
+                    }
+                }
+                Err(e) => eprintln!("Error comparing with the lockfile: {}", e),
+            },
         },
 
         Some(Command::Config(cli_config_cmd)) => {
@@ -596,6 +1371,13 @@ pub fn run(args: Args) {
                 }
 
                 println!("Editor set successfully.");
+            } else if let Some(days) = cli_config_cmd.trash_expiry_days {
+                if let Err(e) = agm.set_trash_expiry_days(days) {
+                    eprintln!("Error setting trash expiry: {}", e);
+                    return;
+                }
+
+                println!("Trash expiry set successfully.");
             } else {
                 eprintln!("Error: No configuration option specified.");
             }
@@ -682,10 +1464,14 @@ pub fn run(args: Args) {
                 println!("Removed mod '{}' from game '{}' profile.", name, game);
 
                 if purge {
-                    println!("Purged mod '{}' from storage for game '{}'.", name, game);
+                    println!("Moved mod '{}' of game '{}' to the trash, 'agm trash restore' brings it back.", name, game);
                 }
             }
 
+            CliMod::Enable { game, name, preset } => set_mod_enabled(&mut agm, &game, &name, preset.as_deref(), true),
+
+            CliMod::Disable { game, name, preset } => set_mod_enabled(&mut agm, &game, &name, preset.as_deref(), false),
+
             CliMod::List { game } => {
                 let mods = agm.get_mods(&game);
                 if mods.is_empty() {
@@ -699,6 +1485,142 @@ pub fn run(args: Args) {
             }
         },
 
+        Some(Command::Verify { game, repair }) => {
+            let result = if repair {
+                agm.repair_game(&game)
+            } else {
+                agm.verify_game(&game)
+            };
+
+            match result {
+                Ok(report) => {
+                    print_verify_report(&game, &report);
+                    if repair && !report.is_clean() {
+                        println!("Repaired game '{}'.", game);
+                    }
+                    if !report.kept.is_empty() {
+                        println!("These files were left in place because an earlier backup of them exists:");
+                        for dest in &report.kept {
+                            println!("  {}", dest.display());
+                        }
+                        println!("Move them out of the way and run 'agm verify {} --repair' again.", game);
+                    }
+                }
+                Err(e) => eprintln!("Error verifying game '{}': {}", game, e),
+            }
+        }
+
+        Some(Command::Snapshot { cmd }) => match cmd {
+            CliSnapshot::Create { game, name } => {
+                let name = name.unwrap_or_else(|| history::format_time(history::now()).replace([' ', ':'], "-"));
+                match agm.create_snapshot(&game, &name) {
+                    Ok(_) => println!("Created snapshot '{}' for game '{}'.", name, game),
+                    Err(e) => eprintln!("Error creating snapshot: {}", e),
+                }
+            }
+
+            CliSnapshot::List { game } => match agm.snapshots(&game) {
+                Ok(snapshots) if snapshots.is_empty() => println!("No snapshots for game '{}'.", game),
+                Ok(snapshots) => {
+                    println!("Snapshots for {}:", game);
+                    for snapshot in snapshots {
+                        let preset = snapshot.active_preset.as_deref().unwrap_or("no active preset");
+                        println!("  - {}  {}  {}", snapshot.name, history::format_time(snapshot.time), preset);
+                    }
+                }
+                Err(e) => eprintln!("Error listing snapshots: {}", e),
+            },
+
+            CliSnapshot::Restore { game, name } => match agm.restore_snapshot(&game, &name) {
+                Ok(verification) => {
+                    println!("Restored snapshot '{}' for game '{}'.", name, game);
+                    if let Some(verification) = verification {
+                        wait_for_verify(&game, verification);
+                    }
+                }
+                Err(Error::SnapshotMismatch(differences)) => {
+                    eprintln!("The installed mods differ from snapshot '{}':", name);
+                    for difference in &differences {
+                        eprintln!("  {}", difference);
+                    }
+                }
+                Err(e) => eprintln!("Error restoring snapshot: {}", e),
+            },
+
+            CliSnapshot::Delete { game, name } => match agm.delete_snapshot(&game, &name) {
+                Ok(()) => println!("Deleted snapshot '{}' for game '{}'.", name, game),
+                Err(e) => eprintln!("Error deleting snapshot: {}", e),
+            },
+        },
+
+        Some(Command::Trash { cmd }) => match cmd {
+            CliTrash::List => match agm.trash_entries() {
+                Ok(entries) if entries.is_empty() => println!("The trash is empty."),
+                Ok(entries) => {
+                    for entry in entries {
+                        let what = match (&entry.mod_name, &entry.file) {
+                            (_, Some(file)) => format!("{} {}", entry.game, file.display()),
+                            (Some(mod_name), None) => format!("{} {}", entry.game, mod_name),
+                            (None, None) => format!("{} (all mods)", entry.game),
+                        };
+                        let size = entry.size().map(format_size).unwrap_or_default();
+                        println!("{}  {}  {}  {}", entry.id, history::format_time(entry.time), what, size);
+                    }
+                }
+                Err(e) => eprintln!("Error reading the trash: {}", e),
+            },
+
+            CliTrash::Restore { id } => match agm.restore_from_trash(&id) {
+                Ok(entry) => match (entry.mod_name, entry.file) {
+                    (_, Some(file)) => println!("Restored '{}'.", file.display()),
+                    (Some(mod_name), None) => println!("Restored mod '{}' for game '{}'.", mod_name, entry.game),
+                    (None, None) => println!("Restored the mods of game '{}'.", entry.game),
+                },
+                Err(e) => eprintln!("Error restoring '{}': {}", id, e),
+            },
+
+            CliTrash::Empty => match agm.empty_trash() {
+                Ok(entries) => println!("Deleted {} trash entr{}.", entries.len(), if entries.len() == 1 { "y" } else { "ies" }),
+                Err(e) => eprintln!("Error emptying the trash: {}", e),
+            },
+        },
+
+        Some(Command::Undo) => match agm.undo() {
+            Ok(Some(replayed)) => {
+                println!("Undid: {}", replayed.operation.description);
+                print_blocked_links(&replayed);
+            }
+            Ok(None) => println!("Nothing to undo."),
+            Err(e) => eprintln!("Error undoing: {}", e),
+        },
+
+        Some(Command::Redo) => match agm.redo() {
+            Ok(Some(replayed)) => {
+                println!("Redid: {}", replayed.operation.description);
+                print_blocked_links(&replayed);
+            }
+            Ok(None) => println!("Nothing to redo."),
+            Err(e) => eprintln!("Error redoing: {}", e),
+        },
+
+        Some(Command::History) => match agm.history() {
+            Ok(history) if history.operations.is_empty() => println!("No history yet."),
+            Ok(history) => {
+                for (i, operation) in history.operations.iter().enumerate() {
+                    let marker = if i + 1 == history.position { "*" } else { " " };
+                    let undone = if i >= history.position { " (undone)" } else { "" };
+                    println!(
+                        "{} {}  {}{}",
+                        marker,
+                        history::format_time(operation.time),
+                        operation.description,
+                        undone
+                    );
+                }
+            }
+            Err(e) => eprintln!("Error reading history: {}", e),
+        },
+
         None => {
             Args::command().print_help().unwrap();
         }